@@ -3,6 +3,7 @@ use rppal::spi::{Bus, Mode, SlaveSelect, Spi};
 
 use m95320::prelude::*;
 use m95320::m95320::Flash;
+use m95320::compat::LegacyDevice;
 
 const GPIO_MEMORY_CHIP_SELECT: u8 = 27;
 
@@ -12,7 +13,7 @@ fn main() {
     let cs = gpio.get(GPIO_MEMORY_CHIP_SELECT).unwrap().into_output();
     let spi = Spi::new(Bus::Spi0, SlaveSelect::Ss0, 10_000_000, Mode::Mode0).unwrap();
 
-    let mut flash = Flash::init(spi, cs).unwrap();
+    let mut flash = Flash::init(LegacyDevice::new(spi, cs).unwrap()).unwrap();
 
     let status = flash.read_status().expect("get status");
     println!("status registers: {:?}", status);
@@ -26,7 +27,7 @@ fn main() {
         page_buffer[i] = byte.clone();
     }
 
-    flash.write_bytes(0, &mut page_buffer).expect("write");
+    flash.write_bytes(0, &page_buffer).expect("write");
     
     flash.read(0, &mut page_buffer).expect("read");
     println!("bytes read: {:?}", page_buffer);
@@ -0,0 +1,254 @@
+//! Several chips on one SPI bus, each selected by its own pin.
+//!
+//! [`FlashArray`] owns the bus and the chip select pins, eg. GPIOs or the pins
+//! of a port expander, and hands out a [`Slot`] for one chip at a time. The bus
+//! has to be an [`SpiDevice`] that does not drive chip select itself, like an
+//! `ExclusiveDevice` with a no-op chip select pin.
+
+use core::ops::{Deref, DerefMut};
+
+use embedded_hal::delay::DelayNs;
+use embedded_hal::digital::OutputPin;
+use embedded_hal::spi::SpiDevice;
+
+use crate::chip::{Chip, M95320};
+use crate::m95320::{write_interleaved, Flash, InterleavedChips, Pins, State};
+use crate::{Error, NoDelay};
+
+/// Chips sharing one SPI bus, with chip select pins `PIN`.
+///
+/// Slots are probed with [`Flash::probe`] when the array is created, and again
+/// by [`FlashArray::rescan`] after cards were inserted or removed. The settings
+/// of each slot, like its [poll mode](Flash::set_poll_mode) or
+/// [fingerprint region](Flash::set_fingerprint_region), are kept by the array
+/// between calls to [`FlashArray::slot`].
+#[derive(Debug)]
+pub struct FlashArray<SPI: SpiDevice, PIN: OutputPin, const N: usize, C: Chip = M95320, D: DelayNs = NoDelay> {
+    spi: SPI,
+    chip: C,
+    chip_selects: [PIN; N],
+    /// Waits between status register polls of every slot, if a provider was
+    /// given.
+    delay: Option<D>,
+    /// Settings and state of the chip in each slot.
+    states: [State; N],
+    /// Whether a chip answered in each slot.
+    present: [bool; N],
+}
+
+impl<SPI: SpiDevice, PIN: OutputPin, const N: usize> FlashArray<SPI, PIN, N> {
+    /// Takes control of the bus and the chip select pins of an array of
+    /// M95320s, and probes each slot.
+    pub fn new(spi: SPI, chip_selects: [PIN; N]) -> Result<Self, Error<SPI, PIN>> {
+        Self::new_with_chip(spi, M95320, chip_selects)
+    }
+}
+
+impl<SPI: SpiDevice, PIN: OutputPin, const N: usize, C: Chip> FlashArray<SPI, PIN, N, C> {
+    /// Takes control of the bus and the chip select pins of an array of other
+    /// chips of the family, and probes each slot.
+    pub fn new_with_chip(spi: SPI, chip: C, chip_selects: [PIN; N]) -> Result<Self, Error<SPI, PIN>> {
+        let mut this = Self {
+            spi,
+            chip,
+            chip_selects,
+            delay: None,
+            states: [State::new::<C>(); N],
+            present: [false; N],
+        };
+
+        // Deselect every chip before talking to any of them
+        for pin in this.chip_selects.iter_mut() {
+            pin.set_high().map_err(Error::Gpio)?;
+        }
+
+        this.rescan()?;
+        Ok(this)
+    }
+
+    /// Sets the delay provider used while waiting for the write cycles of any
+    /// slot, like [`Flash::with_delay`] does for a single chip.
+    pub fn with_delay<D: DelayNs>(self, delay: D) -> FlashArray<SPI, PIN, N, C, D> {
+        FlashArray {
+            spi: self.spi,
+            chip: self.chip,
+            chip_selects: self.chip_selects,
+            delay: Some(delay),
+            states: self.states,
+            present: self.present,
+        }
+    }
+}
+
+impl<SPI: SpiDevice, PIN: OutputPin, const N: usize, C: Chip, D: DelayNs> FlashArray<SPI, PIN, N, C, D> {
+    /// Probes every slot again, eg. after cards were inserted or removed, and
+    /// runs the checks of [`Flash::reattach`] on the chips that answer.
+    ///
+    /// Returns for each slot whether its card changed since the last scan: it
+    /// was inserted, removed, or replaced by one with a different
+    /// [fingerprint](Flash::set_fingerprint_region).
+    ///
+    /// A chip that is still busy with a write cycle when the
+    /// [write timeout](Flash::set_write_timeout_us) hits is taken as removed.
+    /// Without a [delay provider](FlashArray::with_delay), a removed chip whose
+    /// status is unreliable while busy can make this wait forever.
+    pub fn rescan(&mut self) -> Result<[bool; N], Error<SPI, PIN>> {
+        let mut changed = [false; N];
+        for (index, changed) in changed.iter_mut().enumerate() {
+            let (present, replaced) = self.reattach(index)?;
+            *changed = present != self.present[index] || replaced;
+            self.present[index] = present;
+            info!("FlashArray: slot {} present = {}, changed = {}", index, present, *changed);
+        }
+        Ok(changed)
+    }
+
+    /// Returns whether a chip answered in slot `index`.
+    pub fn is_present(&self, index: usize) -> bool {
+        self.present.get(index).copied().unwrap_or(false)
+    }
+
+    /// Returns the indices of the slots a chip answered in.
+    pub fn present_slots(&self) -> impl Iterator<Item = usize> + '_ {
+        (0..N).filter(move |&index| self.present[index])
+    }
+
+    /// Returns the indices of the slots no chip answered in.
+    pub fn empty_slots(&self) -> impl Iterator<Item = usize> + '_ {
+        (0..N).filter(move |&index| !self.present[index])
+    }
+
+    /// Returns a driver for the chip in slot `index`.
+    ///
+    /// The driver keeps the settings it had when the last [`Slot`] of the same
+    /// index was dropped. A write that is still in progress then is waited for
+    /// before the next command to the chip, so other slots can be used during
+    /// its write cycle.
+    ///
+    /// Fails with [`Error::EmptySlot`] if no chip answered in the slot, or
+    /// there is no such slot.
+    pub fn slot(&mut self, index: usize) -> Result<Slot<'_, SPI, PIN, C, D>, Error<SPI, PIN>> {
+        if !self.is_present(index) {
+            return Err(Error::EmptySlot(index));
+        }
+        Ok(self.open(index))
+    }
+
+    /// Writes the same `data` at `addr` on the chip in each of `slots`.
+    ///
+    /// Works like [`Flash::write_interleaved`]: each page is sent to every chip
+    /// in turn, so the chips write in parallel. Returns once all write cycles
+    /// have finished.
+    ///
+    /// Fails with [`Error::EmptySlot`] before anything is sent if one of
+    /// `slots` is empty, or with the errors of [`Flash::write_interleaved`].
+    pub fn write_interleaved(&mut self, slots: &[usize], addr: u32, data: &[u8]) -> Result<(), Error<SPI, PIN>> {
+        for &index in slots {
+            if !self.is_present(index) {
+                return Err(Error::EmptySlot(index));
+            }
+        }
+        write_interleaved::<C, _>(&mut Slots { array: self, indices: slots }, addr, data)
+    }
+
+    /// Waits for the last write cycle of every slot to finish, see
+    /// [`Flash::flush`].
+    pub fn flush(&mut self) -> Result<(), Error<SPI, PIN>> {
+        for index in 0..N {
+            if self.present[index] {
+                self.open(index).flush().map_err(Error::into_owned)?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Returns the descriptor of the chips.
+    pub fn chip(&self) -> C {
+        self.chip
+    }
+
+    /// Releases the bus and the chip select pins.
+    pub fn release(self) -> (SPI, [PIN; N]) {
+        (self.spi, self.chip_selects)
+    }
+
+    /// Returns a driver for slot `index`, whether a chip answered in it or not.
+    fn open(&mut self, index: usize) -> Slot<'_, SPI, PIN, C, D> {
+        let pins = Pins { chip_select: Some(&mut self.chip_selects[index]), ..Pins::default() };
+        let flash = Flash::with_state(&mut self.spi, self.chip, pins, self.delay.as_mut(), self.states[index]);
+        Slot { flash, state: &mut self.states[index] }
+    }
+
+    /// Returns whether a chip answers in slot `index`, and whether it is a
+    /// different card than before according to its fingerprint.
+    fn reattach(&mut self, index: usize) -> Result<(bool, bool), Error<SPI, PIN>> {
+        match self.open(index).reattach() {
+            Ok(replaced) => Ok((true, replaced)),
+            Err(Error::UnexpectedStatus) => Ok((false, false)),
+            Err(e) => Err(e.into_owned()),
+        }
+    }
+}
+
+/// The slots of a [`FlashArray`] taking part in an interleaved write.
+struct Slots<'a, SPI: SpiDevice, PIN: OutputPin, const N: usize, C: Chip, D: DelayNs> {
+    array: &'a mut FlashArray<SPI, PIN, N, C, D>,
+    indices: &'a [usize],
+}
+
+impl<SPI: SpiDevice, PIN: OutputPin, const N: usize, C: Chip, D: DelayNs> InterleavedChips for Slots<'_, SPI, PIN, N, C, D> {
+    type Error = Error<SPI, PIN>;
+
+    fn count(&self) -> usize {
+        self.indices.len()
+    }
+
+    fn check_write(&mut self, index: usize, addr: u32, len: usize) -> Result<(), Self::Error> {
+        self.array.open(self.indices[index]).check_write(addr, len).map_err(Error::into_owned)
+    }
+
+    fn chunk_length(&mut self, index: usize, addr: u32, len: usize) -> usize {
+        self.array.open(self.indices[index]).chunk_length(addr, len)
+    }
+
+    fn send_page(&mut self, index: usize, addr: u32, data: &[u8]) -> Result<(), Self::Error> {
+        self.array.open(self.indices[index]).send_page(addr, data).map_err(Error::into_owned)
+    }
+
+    fn flush(&mut self, index: usize) -> Result<(), Self::Error> {
+        self.array.open(self.indices[index]).flush().map_err(Error::into_owned)
+    }
+}
+
+/// The driver of one chip of a [`FlashArray`], obtained from
+/// [`FlashArray::slot`].
+///
+/// Dereferences to a [`Flash`] that borrows the bus, the chip select pin of the
+/// slot and the delay provider of the array. Its settings are handed back to
+/// the array when it is dropped.
+#[derive(Debug)]
+pub struct Slot<'a, SPI: SpiDevice, PIN: OutputPin, C: Chip, D: DelayNs> {
+    flash: Flash<&'a mut SPI, &'a mut PIN, C, &'a mut D>,
+    /// Where the array keeps the settings of the slot.
+    state: &'a mut State,
+}
+
+impl<'a, SPI: SpiDevice, PIN: OutputPin, C: Chip, D: DelayNs> Deref for Slot<'a, SPI, PIN, C, D> {
+    type Target = Flash<&'a mut SPI, &'a mut PIN, C, &'a mut D>;
+
+    fn deref(&self) -> &Self::Target {
+        &self.flash
+    }
+}
+
+impl<SPI: SpiDevice, PIN: OutputPin, C: Chip, D: DelayNs> DerefMut for Slot<'_, SPI, PIN, C, D> {
+    fn deref_mut(&mut self) -> &mut Self::Target {
+        &mut self.flash
+    }
+}
+
+impl<SPI: SpiDevice, PIN: OutputPin, C: Chip, D: DelayNs> Drop for Slot<'_, SPI, PIN, C, D> {
+    fn drop(&mut self) {
+        *self.state = self.flash.state();
+    }
+}
@@ -0,0 +1,263 @@
+//! Async driver, built on [`embedded-hal-async`](embedded_hal_async).
+//!
+//! [`Flash`] mirrors [`m95320::Flash`](crate::m95320::Flash), but every bus access is
+//! awaited, and waiting for a write cycle to finish yields to the executor through
+//! a [`DelayNs`] provider instead of spinning on the status register.
+
+use core::convert::TryFrom;
+
+use embedded_hal_async::delay::DelayNs;
+use embedded_hal_async::spi::{Operation, SpiDevice};
+
+use crate::chip::{Chip, M95320};
+use crate::m95320::{
+    address_command, default_write_timeout_us, in_bounds, Opcode, ProtectedArea, Status, WrapPolicy,
+    DEFAULT_ERASE_FILL, DEFAULT_POLL_INTERVAL_US,
+};
+use crate::utils::HexSlice;
+use crate::Error;
+
+/// Async driver for M95320 SPI EEPROM chips, and the rest of the M95xxx family.
+///
+/// # Type Parameters
+///
+/// * **`SPI`**: The SPI device the flash chip is attached to. Chip-select is
+///   handled by the device, every command is sent as a single transaction.
+/// * **`D`**: The delay provider used between status register polls.
+/// * **`C`**: The [`Chip`] descriptor of the part. Defaults to the [`M95320`].
+#[derive(Debug)]
+pub struct Flash<SPI: SpiDevice, D: DelayNs, C: Chip = M95320> {
+    spi: SPI,
+    delay: D,
+    chip: C,
+    /// Time to wait between two status register polls.
+    poll_interval_us: u32,
+    /// Time after which a write cycle is abandoned.
+    write_timeout_us: u32,
+    /// Duration of the last write cycle.
+    last_write_cycle_us: Option<u32>,
+    /// Value written to erased bytes.
+    erase_fill: u8,
+    /// Area protected by the `BP1`/`BP0` bits, as last read from the chip.
+    protected: ProtectedArea,
+}
+
+impl<SPI: SpiDevice, D: DelayNs> Flash<SPI, D> {
+    /// Creates the driver and checks that the chip is idle, like
+    /// [`m95320::Flash::init`](crate::m95320::Flash::init).
+    pub async fn init(spi: SPI, delay: D) -> Result<Self, Error<SPI>> {
+        Self::init_with_chip(spi, delay, M95320).await
+    }
+}
+
+impl<SPI: SpiDevice, D: DelayNs, C: Chip> Flash<SPI, D, C> {
+    /// Creates the driver for another chip of the family, like
+    /// [`m95320::Flash::init_with_chip`](crate::m95320::Flash::init_with_chip).
+    pub async fn init_with_chip(spi: SPI, delay: D, chip: C) -> Result<Self, Error<SPI>> {
+        let mut this = Self {
+            spi,
+            delay,
+            chip,
+            poll_interval_us: DEFAULT_POLL_INTERVAL_US,
+            write_timeout_us: default_write_timeout_us::<C>(),
+            last_write_cycle_us: None,
+            erase_fill: DEFAULT_ERASE_FILL,
+            protected: ProtectedArea::None,
+        };
+        let status = this.read_status().await?;
+        info!("asynch::Flash::init: status = {:?}", status);
+        this.protected = status.protected_area();
+
+        // Here we don't expect any writes to be in progress
+        if !(status & (Status::WRITE_IN_PROGRESS)).is_empty() {
+            return Err(Error::UnexpectedStatus);
+        }
+
+        if !(status & (Status::WRITE_ENABLE_LATCH)).is_empty() {
+            warn!("Write Enable Latch was set on init! Going to assume we're okay and disable it");
+            let result = this.write_disable().await;
+            #[allow(clippy::single_match)]
+            match result {
+                Err(_) => return Err(Error::UnexpectedStatus),
+                Ok(_) => (),
+            };
+        }
+
+        Ok(this)
+    }
+
+    /// Returns the SPI device and the delay provider.
+    pub fn release(self) -> (SPI, D) {
+        (self.spi, self.delay)
+    }
+
+    /// Returns the descriptor of the chip.
+    pub fn chip(&self) -> C {
+        self.chip
+    }
+
+    /// Sets the time to wait between two status register polls, like
+    /// [`m95320::Flash::set_poll_interval_us`](crate::m95320::Flash::set_poll_interval_us).
+    pub fn set_poll_interval_us(&mut self, interval_us: u32) {
+        self.poll_interval_us = interval_us;
+    }
+
+    /// Sets the time after which a write cycle fails with [`Error::Timeout`], like
+    /// [`m95320::Flash::set_write_timeout_us`](crate::m95320::Flash::set_write_timeout_us).
+    pub fn set_write_timeout_us(&mut self, timeout_us: u32) {
+        self.write_timeout_us = timeout_us;
+    }
+
+    /// Sets the value written to erased bytes, like
+    /// [`m95320::Flash::set_erase_fill`](crate::m95320::Flash::set_erase_fill).
+    pub fn set_erase_fill(&mut self, fill: u8) {
+        self.erase_fill = fill;
+    }
+
+    /// Returns how long the last write cycle took, in steps of the poll
+    /// interval.
+    pub fn last_write_cycle_us(&self) -> Option<u32> {
+        self.last_write_cycle_us
+    }
+
+    async fn command(&mut self, operations: &mut [Operation<'_, u8>]) -> Result<(), Error<SPI>> {
+        self.spi.transaction(operations).await.map_err(Error::Spi)
+    }
+
+    /// Reads the status register.
+    pub async fn read_status(&mut self) -> Result<Status, Error<SPI>> {
+        let mut buf = [0];
+        self.command(&mut [
+            Operation::Write(&[Opcode::ReadStatusRegister as u8]),
+            Operation::Read(&mut buf),
+        ])
+        .await?;
+
+        Ok(Status::from_chip::<C>(buf[0]))
+    }
+
+    /// Returns the write protected area, like
+    /// [`m95320::Flash::protected_area`](crate::m95320::Flash::protected_area).
+    pub async fn protected_area(&mut self) -> Result<ProtectedArea, Error<SPI>> {
+        self.protected = self.read_status().await?.protected_area();
+        Ok(self.protected)
+    }
+
+    async fn write_enable(&mut self) -> Result<(), Error<SPI>> {
+        self.command(&mut [Operation::Write(&[Opcode::WriteEnable as u8])]).await
+    }
+
+    async fn write_disable(&mut self) -> Result<(), Error<SPI>> {
+        self.command(&mut [Operation::Write(&[Opcode::WriteDisable as u8])]).await
+    }
+
+    async fn wait_done(&mut self) -> Result<(), Error<SPI>> {
+        let mut elapsed_us = 0;
+        while self.read_status().await?.contains(Status::WRITE_IN_PROGRESS) {
+            if elapsed_us >= self.write_timeout_us {
+                warn!("write cycle did not finish within {} us", elapsed_us);
+                return Err(Error::Timeout(elapsed_us));
+            }
+            self.delay.delay_us(self.poll_interval_us).await;
+            elapsed_us = elapsed_us.saturating_add(self.poll_interval_us);
+        }
+
+        self.last_write_cycle_us = Some(elapsed_us);
+        Ok(())
+    }
+
+    async fn write_bytes_to_page(&mut self, addr: u32, data: &[u8]) -> Result<(), Error<SPI>> {
+        if !in_bounds::<C>(addr, data.len(), WrapPolicy::Reject) {
+            return Err(Error::AddressOutOfBounds { start: addr, len: data.len() })
+        }
+
+        self.write_enable().await?;
+
+        let (cmd_buf, cmd_len) = address_command::<C>(Opcode::Write, addr);
+
+        self.command(&mut [Operation::Write(&cmd_buf[..cmd_len]), Operation::Write(data)]).await?;
+
+        self.wait_done().await
+    }
+
+    /// Fails with [`Error::AddressOutOfBounds`] if `len` bytes starting at
+    /// `addr` run past the end of the memory array.
+    fn check_range(&self, addr: u32, len: usize) -> Result<(), Error<SPI>> {
+        if in_bounds::<C>(addr, len, WrapPolicy::Reject) {
+            Ok(())
+        } else {
+            Err(Error::AddressOutOfBounds { start: addr, len })
+        }
+    }
+
+    /// Checks that `len` bytes starting at `addr` are in bounds and not write
+    /// protected, before anything is sent to the chip.
+    fn check_write(&self, addr: u32, len: usize) -> Result<(), Error<SPI>> {
+        self.check_range(addr, len)?;
+        if self.protected.overlaps::<C>(addr, len) {
+            return Err(Error::WriteProtected(addr));
+        }
+        Ok(())
+    }
+
+    /// Reads `buf.len()` bytes starting at `addr`.
+    pub async fn read(&mut self, addr: u32, buf: &mut [u8]) -> Result<(), Error<SPI>> {
+        self.check_range(addr, buf.len())?;
+        let (cmd_buf, cmd_len) = address_command::<C>(Opcode::Read, addr);
+
+        self.command(&mut [Operation::Write(&cmd_buf[..cmd_len]), Operation::Read(buf)]).await?;
+
+        trace!("read {:#05x}: {:?}", addr, HexSlice(&*buf));
+        Ok(())
+    }
+
+    /// Writes `data` starting at `addr`, one page at a time.
+    ///
+    /// Fails with [`Error::WriteProtected`] before anything is sent if the
+    /// range touches the protected area.
+    pub async fn write_bytes(&mut self, addr: u32, data: &[u8]) -> Result<(), Error<SPI>> {
+        self.check_write(addr, data.len())?;
+        let page_size = u32::from(C::PAGE_SIZE);
+        let mut current_addr = addr;
+        let mut rest_of_data = data;
+
+        while !rest_of_data.is_empty() {
+            let chunk_length = ((page_size - current_addr % page_size) as usize).min(rest_of_data.len());
+            let (chunk_data, rest) = rest_of_data.split_at(chunk_length);
+
+            self.write_bytes_to_page(current_addr, chunk_data).await?;
+
+            current_addr += chunk_length as u32;
+            rest_of_data = rest;
+        }
+
+        Ok(())
+    }
+
+    /// Erases `amount` whole pages starting at `addr`, which has to be on a page
+    /// boundary, by writing the erase fill value to them.
+    pub async fn erase_sectors(&mut self, addr: u32, amount: usize) -> Result<(), Error<SPI>> {
+        if addr % u32::from(C::PAGE_SIZE) != 0 {
+            return Err(Error::NotPageAligned(addr));
+        }
+        let len = usize::try_from((amount as u64).saturating_mul(C::PAGE_SIZE.into())).unwrap_or(usize::MAX);
+        self.check_write(addr, len)?;
+
+        let erased = [self.erase_fill; 512];
+        let mut current_addr = addr;
+        let mut remaining = len;
+        while remaining > 0 {
+            let chunk_length = remaining.min(erased.len());
+            self.write_bytes(current_addr, &erased[..chunk_length]).await?;
+            current_addr += chunk_length as u32;
+            remaining -= chunk_length;
+        }
+
+        Ok(())
+    }
+
+    /// Erases every page of the chip.
+    pub async fn erase_all(&mut self) -> Result<(), Error<SPI>> {
+        self.erase_sectors(0, (C::CAPACITY / u32::from(C::PAGE_SIZE)) as usize).await
+    }
+}
@@ -0,0 +1,131 @@
+//! Descriptors for the chips of the M95xxx family.
+//!
+//! The parts differ in capacity, page size and the number of address bytes sent
+//! after each instruction, but otherwise share the same instruction set. A
+//! [`Flash`](crate::m95320::Flash) is generic over a [`Chip`] that describes
+//! the part it drives, and defaults to the [`M95320`].
+//!
+//! Pin and instruction compatible 32-Kbit EEPROMs from other vendors are
+//! described by [`MC25LC320`], [`AT25320`] and [`CAT25320`], so the same driver
+//! can be used with second-source parts. Their geometry and maximum write cycle
+//! time of 5 ms are the same as the M95320's, so the profiles only differ where
+//! the status register does, like the [`AT25320`]'s during a write cycle.
+
+/// Describes the geometry and timing of an SPI EEPROM of the M95xxx family.
+///
+/// Implemented for the parts listed in this module, and can be implemented for
+/// other compatible chips.
+pub trait Chip: Copy {
+    /// Size of the memory array in bytes.
+    const CAPACITY: u32;
+
+    /// Size of a page in bytes. A single write can't cross a page boundary.
+    const PAGE_SIZE: u16;
+
+    /// Number of address bytes sent after the instruction.
+    ///
+    /// With a single address byte and more than 256 bytes of memory, the ninth
+    /// address bit is sent as bit 3 of the instruction, like on the M95040.
+    const ADDRESS_BYTES: u8;
+
+    /// Maximum duration of a write cycle (tW) in microseconds.
+    const WRITE_CYCLE_TIME_US: u32;
+
+    /// Whether the status register bits other than `WIP` can be trusted while
+    /// a write cycle is in progress. Some parts read as `0xFF` instead.
+    const STATUS_VALID_WHILE_BUSY: bool = true;
+}
+
+macro_rules! chips {
+    ($($(#[$attr:meta])* $name:ident: $capacity:expr, $page_size:expr, $address_bytes:expr, $write_cycle_time_us:expr;)*) => {
+        $(
+            $(#[$attr])*
+            #[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+            pub struct $name;
+
+            impl Chip for $name {
+                const CAPACITY: u32 = $capacity;
+                const PAGE_SIZE: u16 = $page_size;
+                const ADDRESS_BYTES: u8 = $address_bytes;
+                const WRITE_CYCLE_TIME_US: u32 = $write_cycle_time_us;
+            }
+        )*
+    };
+}
+
+chips! {
+    /// 1-Kbit (128 byte) EEPROM with 16 byte pages.
+    M95010: 128, 16, 1, 5_000;
+    /// 2-Kbit (256 byte) EEPROM with 16 byte pages.
+    M95020: 256, 16, 1, 5_000;
+    /// 4-Kbit (512 byte) EEPROM with 16 byte pages.
+    M95040: 512, 16, 1, 5_000;
+    /// 8-Kbit (1 KiB) EEPROM with 32 byte pages.
+    M95080: 1024, 32, 2, 5_000;
+    /// 16-Kbit (2 KiB) EEPROM with 32 byte pages.
+    M95160: 2048, 32, 2, 5_000;
+    /// 32-Kbit (4 KiB) EEPROM with 32 byte pages.
+    M95320: 4096, 32, 2, 5_000;
+    /// 64-Kbit (8 KiB) EEPROM with 32 byte pages.
+    M95640: 8192, 32, 2, 5_000;
+    /// 128-Kbit (16 KiB) EEPROM with 64 byte pages.
+    M95128: 16_384, 64, 2, 5_000;
+    /// 256-Kbit (32 KiB) EEPROM with 64 byte pages.
+    M95256: 32_768, 64, 2, 5_000;
+    /// 512-Kbit (64 KiB) EEPROM with 128 byte pages.
+    M95512: 65_536, 128, 2, 5_000;
+    /// 1-Mbit (128 KiB) EEPROM with 256 byte pages.
+    M95M01: 131_072, 256, 3, 5_000;
+    /// 2-Mbit (256 KiB) EEPROM with 256 byte pages.
+    M95M02: 262_144, 256, 3, 10_000;
+    /// 4-Mbit (512 KiB) EEPROM with 512 byte pages.
+    M95M04: 524_288, 512, 3, 10_000;
+}
+
+/// Microchip 25LC320A (and 25AA320A), a 32-Kbit EEPROM with 32 byte pages
+/// and a write cycle time (tWC) of 5 ms.
+///
+/// Bit 7 of the status register is called `WPEN`, see
+/// [`Status::WRITE_PROTECT_ENABLE`](crate::m95320::Status::WRITE_PROTECT_ENABLE).
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct MC25LC320;
+
+impl Chip for MC25LC320 {
+    const CAPACITY: u32 = 4096;
+    const PAGE_SIZE: u16 = 32;
+    const ADDRESS_BYTES: u8 = 2;
+    const WRITE_CYCLE_TIME_US: u32 = 5_000;
+}
+
+/// Atmel (Microchip) AT25320B, a 32-Kbit EEPROM with 32 byte pages and a
+/// write cycle time (tWC) of 5 ms.
+///
+/// Bit 7 of the status register is called `WPEN`, see
+/// [`Status::WRITE_PROTECT_ENABLE`](crate::m95320::Status::WRITE_PROTECT_ENABLE). The whole status register reads as `0xFF`
+/// during a write cycle.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct AT25320;
+
+impl Chip for AT25320 {
+    const CAPACITY: u32 = 4096;
+    const PAGE_SIZE: u16 = 32;
+    const ADDRESS_BYTES: u8 = 2;
+    const WRITE_CYCLE_TIME_US: u32 = 5_000;
+    const STATUS_VALID_WHILE_BUSY: bool = false;
+}
+
+/// onsemi CAT25320, a 32-Kbit EEPROM with 32 byte pages and a write cycle
+/// time (tWC) of 5 ms.
+///
+/// Bit 7 of the status register is called `WPEN`, see
+/// [`Status::WRITE_PROTECT_ENABLE`](crate::m95320::Status::WRITE_PROTECT_ENABLE), and bit 0 `RDY`, which is set while busy
+/// like `WIP`.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct CAT25320;
+
+impl Chip for CAT25320 {
+    const CAPACITY: u32 = 4096;
+    const PAGE_SIZE: u16 = 32;
+    const ADDRESS_BYTES: u8 = 2;
+    const WRITE_CYCLE_TIME_US: u32 = 5_000;
+}
@@ -0,0 +1,259 @@
+//! Support for `embedded-hal` 0.2 buses and pins.
+//!
+//! [`LegacyDevice`] pairs an `embedded-hal` 0.2 SPI bus with the chip-select pin of
+//! the memory chip and implements the `embedded-hal` 1.0 [`SpiDevice`] trait on top of
+//! them, driving chip-select low for the duration of each transaction.
+//!
+//! Buses that drive chip-select in hardware, like spidev with a kernel-managed
+//! `CE` line, deselect the chip after every transfer. For those,
+//! [`LegacyDevice::new_single_transfer`] sends each transaction as one transfer,
+//! and [`Flash::init_single_transfer`] splits reads and writes into
+//! transactions that fit.
+
+use core::convert::Infallible;
+use core::fmt::{self, Debug, Display};
+
+use embedded_hal::spi::{self, ErrorKind, ErrorType, Operation, SpiDevice};
+use embedded_hal_02::blocking::spi::Transfer;
+use embedded_hal_02::digital::v2::OutputPin;
+
+use crate::chip::{Chip, M95320};
+use crate::m95320::Flash;
+use crate::{Error, NoPin};
+
+/// Number of bytes copied onto the stack at a time for write operations, since
+/// `Transfer` overwrites the buffer it is given.
+const CHUNK_SIZE: usize = 32;
+
+/// Maximum length of a transaction in single-transfer mode: an instruction with
+/// three address bytes, followed by a page of the M95M04, the largest of the
+/// family.
+pub const SINGLE_TRANSFER_SIZE: usize = 4 + 512;
+
+/// An `embedded-hal` 0.2 SPI bus and chip-select pin, used as an `embedded-hal` 1.0
+/// [`SpiDevice`].
+///
+/// # Type Parameters
+///
+/// * **`SPI`**: The SPI master to which the flash chip is attached.
+/// * **`CS`**: The **C**hip-**S**elect line attached to the `\CS`/`\CE` pin of
+///   the flash chip. [`NoPin`] when the bus drives chip-select itself.
+#[derive(Debug)]
+pub struct LegacyDevice<SPI, CS = NoPin> {
+    spi: SPI,
+    cs: CS,
+    /// Whether each transaction is packed into a single transfer.
+    single_transfer: bool,
+}
+
+impl<SPI: Transfer<u8>> LegacyDevice<SPI> {
+    /// Creates a device from a bus that drives chip-select in hardware for the
+    /// duration of each transfer.
+    ///
+    /// Every transaction is copied into a buffer on the stack and sent as one
+    /// transfer, so transactions are limited to [`SINGLE_TRANSFER_SIZE`] bytes.
+    /// Longer ones fail with [`LegacyError::TooLong`] without touching the bus.
+    ///
+    /// [`Flash::init_single_transfer`] creates the device and limits the
+    /// driver's transactions to fit, like
+    /// [`Flash::set_max_transaction_size`] does.
+    pub fn new_single_transfer(spi: SPI) -> Self {
+        Self { spi, cs: NoPin, single_transfer: true }
+    }
+}
+
+impl<SPI: Transfer<u8>, CS: OutputPin> LegacyDevice<SPI, CS> {
+    /// Creates a device from `spi` and `cs`, deselecting the chip.
+    pub fn new(spi: SPI, mut cs: CS) -> Result<Self, LegacyError<SPI::Error, CS::Error>> {
+        cs.set_high().map_err(LegacyError::Gpio)?;
+        Ok(Self { spi, cs, single_transfer: false })
+    }
+
+    /// Returns the bus and the chip-select pin.
+    pub fn release(self) -> (SPI, CS) {
+        (self.spi, self.cs)
+    }
+
+    fn run(&mut self, operation: &mut Operation<'_, u8>) -> Result<(), SPI::Error> {
+        match operation {
+            Operation::Read(buf) => {
+                buf.iter_mut().for_each(|byte| *byte = 0);
+                self.spi.transfer(buf)?;
+            }
+            Operation::Write(data) => {
+                self.write(data)?;
+            }
+            Operation::Transfer(read, write) => {
+                let common = read.len().min(write.len());
+                read[..common].copy_from_slice(&write[..common]);
+                read[common..].iter_mut().for_each(|byte| *byte = 0);
+                self.spi.transfer(read)?;
+                self.write(&write[common..])?;
+            }
+            Operation::TransferInPlace(buf) => {
+                self.spi.transfer(buf)?;
+            }
+            // Rejected by `transaction` before the chip is selected
+            Operation::DelayNs(_) => {}
+        }
+        Ok(())
+    }
+
+    fn write(&mut self, data: &[u8]) -> Result<(), SPI::Error> {
+        for chunk in data.chunks(CHUNK_SIZE) {
+            let mut buf = [0; CHUNK_SIZE];
+            buf[..chunk.len()].copy_from_slice(chunk);
+            self.spi.transfer(&mut buf[..chunk.len()])?;
+        }
+        Ok(())
+    }
+
+    /// Sends all `operations` as one transfer.
+    fn run_packed(&mut self, operations: &mut [Operation<'_, u8>]) -> Result<(), LegacyError<SPI::Error, CS::Error>> {
+        let mut packed = [0; SINGLE_TRANSFER_SIZE];
+        let mut len = 0;
+        for operation in operations.iter() {
+            let (write, op_len): (&[u8], usize) = match operation {
+                Operation::Read(buf) => (&[], buf.len()),
+                Operation::Write(data) => (data, data.len()),
+                Operation::Transfer(read, write) => (write, read.len().max(write.len())),
+                Operation::TransferInPlace(buf) => (buf, buf.len()),
+                Operation::DelayNs(_) => (&[], 0),
+            };
+            if len + op_len > SINGLE_TRANSFER_SIZE {
+                return Err(LegacyError::TooLong);
+            }
+            packed[len..len + write.len()].copy_from_slice(write);
+            len += op_len;
+        }
+
+        self.spi.transfer(&mut packed[..len]).map_err(LegacyError::Spi)?;
+
+        let mut offset = 0;
+        for operation in operations.iter_mut() {
+            match operation {
+                Operation::Read(buf) | Operation::TransferInPlace(buf) => {
+                    buf.copy_from_slice(&packed[offset..offset + buf.len()]);
+                    offset += buf.len();
+                }
+                Operation::Transfer(read, write) => {
+                    read.copy_from_slice(&packed[offset..offset + read.len()]);
+                    offset += read.len().max(write.len());
+                }
+                Operation::Write(data) => offset += data.len(),
+                Operation::DelayNs(_) => {}
+            }
+        }
+        Ok(())
+    }
+}
+
+impl<SPI: Transfer<u8>, CS: OutputPin> ErrorType for LegacyDevice<SPI, CS>
+where
+    SPI::Error: Debug,
+    CS::Error: Debug,
+{
+    type Error = LegacyError<SPI::Error, CS::Error>;
+}
+
+impl<SPI: Transfer<u8>, CS: OutputPin> SpiDevice for LegacyDevice<SPI, CS>
+where
+    SPI::Error: Debug,
+    CS::Error: Debug,
+{
+    /// Fails with [`LegacyError::Unsupported`] without touching the bus if
+    /// `operations` contains a delay, since there is no delay provider.
+    fn transaction(&mut self, operations: &mut [Operation<'_, u8>]) -> Result<(), Self::Error> {
+        if operations.iter().any(|operation| matches!(operation, Operation::DelayNs(_))) {
+            return Err(LegacyError::Unsupported);
+        }
+        if self.single_transfer {
+            return self.run_packed(operations);
+        }
+
+        // If an SPI transfer fails, make sure to disable CS anyways
+        self.cs.set_low().map_err(LegacyError::Gpio)?;
+        let spi_result = operations.iter_mut().try_for_each(|operation| self.run(operation));
+        self.cs.set_high().map_err(LegacyError::Gpio)?;
+        spi_result.map_err(LegacyError::Spi)
+    }
+}
+
+impl<SPI: Transfer<u8>> Flash<LegacyDevice<SPI>>
+where
+    SPI::Error: Debug,
+{
+    /// Initializes the driver for an M95320 on a bus that drives chip-select in
+    /// hardware, see [`LegacyDevice::new_single_transfer`].
+    ///
+    /// Reads and writes are split into transactions of at most
+    /// [`SINGLE_TRANSFER_SIZE`] bytes.
+    pub fn init_single_transfer(spi: SPI) -> Result<Self, Error<LegacyDevice<SPI>>> {
+        Self::init_single_transfer_with_chip(spi, M95320)
+    }
+}
+
+impl<SPI: Transfer<u8>, C: Chip> Flash<LegacyDevice<SPI>, NoPin, C>
+where
+    SPI::Error: Debug,
+{
+    /// Initializes the driver for another chip of the family on a bus that
+    /// drives chip-select in hardware, like [`Flash::init_single_transfer`].
+    pub fn init_single_transfer_with_chip(spi: SPI, chip: C) -> Result<Self, Error<LegacyDevice<SPI>>> {
+        let mut flash = Flash::init_with_chip(LegacyDevice::new_single_transfer(spi), chip)?;
+        flash.set_max_transaction_size(SINGLE_TRANSFER_SIZE);
+        Ok(flash)
+    }
+}
+
+impl OutputPin for NoPin {
+    type Error = Infallible;
+
+    fn set_low(&mut self) -> Result<(), Self::Error> {
+        Ok(())
+    }
+
+    fn set_high(&mut self) -> Result<(), Self::Error> {
+        Ok(())
+    }
+}
+
+/// The error type of a [`LegacyDevice`].
+#[derive(Debug)]
+pub enum LegacyError<SPI, GPIO> {
+    /// An SPI transfer failed.
+    Spi(SPI),
+
+    /// The chip-select pin could not be set.
+    Gpio(GPIO),
+
+    /// A transaction was longer than [`SINGLE_TRANSFER_SIZE`] in single-transfer
+    /// mode.
+    TooLong,
+
+    /// A transaction contained an operation the device can't perform, like a
+    /// delay.
+    Unsupported,
+}
+
+impl<SPI: Debug, GPIO: Debug> spi::Error for LegacyError<SPI, GPIO> {
+    fn kind(&self) -> ErrorKind {
+        match self {
+            LegacyError::Spi(_) => ErrorKind::Other,
+            LegacyError::Gpio(_) => ErrorKind::ChipSelectFault,
+            LegacyError::TooLong => ErrorKind::Other,
+            LegacyError::Unsupported => ErrorKind::Other,
+        }
+    }
+}
+
+impl<SPI: Display, GPIO: Display> Display for LegacyError<SPI, GPIO> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            LegacyError::Spi(spi) => write!(f, "SPI error: {}", spi),
+            LegacyError::Gpio(gpio) => write!(f, "GPIO error: {}", gpio),
+            LegacyError::TooLong => write!(f, "transaction longer than {} bytes", SINGLE_TRANSFER_SIZE),
+            LegacyError::Unsupported => write!(f, "unsupported operation"),
+        }
+    }
+}
@@ -0,0 +1,13 @@
+use embedded_hal::delay::DelayNs;
+
+/// A missing delay provider.
+///
+/// This is the default for the delay provider of
+/// [`Flash`](crate::m95320::Flash). Without one, the driver polls the status
+/// register back to back and can't time out. Delaying with it does nothing.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct NoDelay;
+
+impl DelayNs for NoDelay {
+    fn delay_ns(&mut self, _ns: u32) {}
+}
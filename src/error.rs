@@ -1,6 +1,7 @@
 use core::fmt::{self, Debug, Display};
-use embedded_hal::blocking::spi::Transfer;
-use embedded_hal::digital::v2::OutputPin;
+use embedded_hal::{digital, spi};
+
+use crate::NoPin;
 
 mod private {
     #[derive(Debug)]
@@ -11,8 +12,8 @@ mod private {
 ///
 /// This can encapsulate an SPI or GPIO error, and adds its own protocol errors
 /// on top of that.
-pub enum Error<SPI: Transfer<u8>, GPIO: OutputPin> {
-    /// An SPI transfer failed.
+pub enum Error<SPI: spi::ErrorType, GPIO: digital::ErrorType = NoPin> {
+    /// An SPI transaction failed.
     Spi(SPI::Error),
 
     /// A GPIO could not be set.
@@ -27,30 +28,102 @@ pub enum Error<SPI: Transfer<u8>, GPIO: OutputPin> {
 
     /// Address Out of Bounds
     /// 
-    /// Tried to address memory beyond the limit of the peripheral
-    AddressOutOfBounds(u128),
+    /// Tried to access `len` bytes starting at `start`, which goes beyond the
+    /// limit of the peripheral
+    AddressOutOfBounds {
+        /// First address of the access.
+        start: u32,
+        /// Number of bytes accessed.
+        len: usize,
+    },
+
+    /// Write Protected
+    ///
+    /// Tried to write, starting at the given address, into the area protected by
+    /// the block protect bits of the status register
+    WriteProtected(u32),
+
+    /// Identification Page Locked
+    ///
+    /// Tried to write to the Identification Page after it was locked
+    IdPageLocked,
+
+    /// Missing Pin
+    ///
+    /// The operation needs a pin that was not passed to the driver
+    MissingPin,
+
+    /// Not Page Aligned
+    ///
+    /// Tried to erase starting at the given address, which is not on a page
+    /// boundary
+    NotPageAligned(u32),
+
+    /// Timeout
+    ///
+    /// A write cycle was still in progress after the given number of
+    /// microseconds
+    Timeout(u32),
+
+    /// Empty Slot
+    ///
+    /// No chip answered in the given slot of a
+    /// [`FlashArray`](crate::array::FlashArray)
+    EmptySlot(usize),
+
+    /// Range Too Small
+    ///
+    /// The address range `start..end` is too small for a map or queue, which
+    /// need at least two pages
+    RangeTooSmall {
+        /// First address of the range.
+        start: u32,
+        /// Address after the end of the range.
+        end: u32,
+    },
+
+    /// Read Too Long
+    ///
+    /// Tried to read `len` bytes in one transaction, more than the `max` bytes
+    /// that fit in the transfers of one transaction when they are limited by
+    /// [`Flash::set_max_transfer_size`](crate::m95320::Flash::set_max_transfer_size),
+    /// without a chip select pin to keep the chip selected in between
+    ReadTooLong {
+        /// Number of bytes read.
+        len: usize,
+        /// Number of bytes that can be read in one transaction.
+        max: usize,
+    },
 
     #[doc(hidden)]
     __NonExhaustive(private::Private),
 }
 
-impl<SPI: Transfer<u8>, GPIO: OutputPin> Debug for Error<SPI, GPIO>
-where
-    SPI::Error: Debug,
-    GPIO::Error: Debug,
-{
+impl<SPI: spi::ErrorType, GPIO: digital::ErrorType> Debug for Error<SPI, GPIO> {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         match self {
             Error::Spi(spi) => write!(f, "Error::Spi({:?})", spi),
             Error::Gpio(gpio) => write!(f, "Error::Gpio({:?})", gpio),
             Error::UnexpectedStatus => f.write_str("Error::UnexpectedStatus"),
-            Error::AddressOutOfBounds(addr) => write!(f, "Error:AddressOutOfBounds({:?})", addr),
+            Error::AddressOutOfBounds { start, len } => {
+                write!(f, "Error::AddressOutOfBounds {{ start: {:#05x}, len: {} }}", start, len)
+            }
+            Error::WriteProtected(addr) => write!(f, "Error::WriteProtected({:#05x})", addr),
+            Error::IdPageLocked => f.write_str("Error::IdPageLocked"),
+            Error::MissingPin => f.write_str("Error::MissingPin"),
+            Error::NotPageAligned(addr) => write!(f, "Error::NotPageAligned({:#05x})", addr),
+            Error::Timeout(elapsed_us) => write!(f, "Error::Timeout({:?})", elapsed_us),
+            Error::EmptySlot(index) => write!(f, "Error::EmptySlot({})", index),
+            Error::RangeTooSmall { start, end } => {
+                write!(f, "Error::RangeTooSmall {{ start: {:#05x}, end: {:#05x} }}", start, end)
+            }
+            Error::ReadTooLong { len, max } => write!(f, "Error::ReadTooLong {{ len: {}, max: {} }}", len, max),
             Error::__NonExhaustive(_) => unreachable!(),
         }
     }
 }
 
-impl<SPI: Transfer<u8>, GPIO: OutputPin> Display for Error<SPI, GPIO>
+impl<SPI: spi::ErrorType, GPIO: digital::ErrorType> Display for Error<SPI, GPIO>
 where
     SPI::Error: Display,
     GPIO::Error: Display,
@@ -60,8 +133,40 @@ where
             Error::Spi(spi) => write!(f, "SPI error: {}", spi),
             Error::Gpio(gpio) => write!(f, "GPIO error: {}", gpio),
             Error::UnexpectedStatus => f.write_str("unexpected value in status register"),
-            Error::AddressOutOfBounds(addr) => write!(f, "Error:AddressOutOfBounds({:?})", addr),
+            Error::AddressOutOfBounds { start, len } => {
+                write!(f, "{} bytes starting at {:#05x} are out of bounds", len, start)
+            }
+            Error::WriteProtected(addr) => write!(f, "write at {:#05x} hits a block protected area", addr),
+            Error::IdPageLocked => f.write_str("the identification page is locked"),
+            Error::MissingPin => f.write_str("no pin was passed to the driver for this operation"),
+            Error::NotPageAligned(addr) => write!(f, "address {:#05x} is not on a page boundary", addr),
+            Error::Timeout(elapsed_us) => write!(f, "write cycle did not finish within {} µs", elapsed_us),
+            Error::EmptySlot(index) => write!(f, "no chip answered in slot {}", index),
+            Error::RangeTooSmall { start, end } => write!(f, "range {:#05x}..{:#05x} is smaller than two pages", start, end),
+            Error::ReadTooLong { len, max } => write!(f, "read of {} bytes does not fit in one transaction of {} bytes", len, max),
             Error::__NonExhaustive(_) => unreachable!(),
         }
     }
 }
+
+impl<'a, SPI: spi::ErrorType, GPIO: digital::ErrorType> Error<&'a mut SPI, &'a mut GPIO> {
+    /// Converts the error of a driver that borrows its bus and pins, like a
+    /// [`Slot`](crate::array::Slot), into an error of the owned types.
+    pub fn into_owned(self) -> Error<SPI, GPIO> {
+        match self {
+            Error::Spi(spi) => Error::Spi(spi),
+            Error::Gpio(gpio) => Error::Gpio(gpio),
+            Error::UnexpectedStatus => Error::UnexpectedStatus,
+            Error::AddressOutOfBounds { start, len } => Error::AddressOutOfBounds { start, len },
+            Error::WriteProtected(addr) => Error::WriteProtected(addr),
+            Error::IdPageLocked => Error::IdPageLocked,
+            Error::MissingPin => Error::MissingPin,
+            Error::NotPageAligned(addr) => Error::NotPageAligned(addr),
+            Error::Timeout(elapsed_us) => Error::Timeout(elapsed_us),
+            Error::EmptySlot(index) => Error::EmptySlot(index),
+            Error::RangeTooSmall { start, end } => Error::RangeTooSmall { start, end },
+            Error::ReadTooLong { len, max } => Error::ReadTooLong { len, max },
+            Error::__NonExhaustive(private) => Error::__NonExhaustive(private),
+        }
+    }
+}
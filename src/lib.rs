@@ -2,6 +2,27 @@
 //! 
 //! *some features not yet implemented, basic read and write is working*
 //! 
+//! The driver talks to the chip through an `embedded-hal` 1.0 [`SpiDevice`](embedded_hal::spi::SpiDevice), which
+//! owns chip-select. Buses and pins implementing the `embedded-hal` 0.2 traits can be
+//! used through [`compat::LegacyDevice`] with the `embedded-hal-02` feature.
+//! 
+//! With the `async` feature, [`asynch::Flash`] provides the same operations on top of
+//! `embedded-hal-async`.
+//! 
+//! Other chips of the M95xxx family are supported by passing a [`chip`] descriptor
+//! to [`m95320::Flash::init_with_chip`]; the driver defaults to the M95320.
+//! 
+//! Several chips sharing one bus can be driven through an [`array::FlashArray`].
+//! 
+//! With the `embedded-storage` feature, [`m95320::Flash`] also implements the
+//! `embedded-storage` traits, including the NOR flash ones. The
+//! `sequential-storage` feature builds a key/value map and a queue on top of
+//! them, see [`sequential`].
+//! 
+//! With the `spi-memory` feature, a driver on top of a [`compat::LegacyDevice`]
+//! implements the `Read` and `BlockDevice` traits of the `spi-memory` crate, so
+//! code written for its SPI flash drivers can use the EEPROM instead.
+//! 
 // ! This create is mostly ripped-off from the `spi-memory` crate: https://github.com/jonas-schievink/spi-memory
 
 #![doc(html_root_url = "https://docs.rs/m95320/1.0.0")]
@@ -10,46 +31,75 @@
 
 #[macro_use]
 mod log;
+pub mod array;
+#[cfg(feature = "async")]
+pub mod asynch;
+pub mod chip;
+#[cfg(feature = "embedded-hal-02")]
+pub mod compat;
+mod delay;
 mod error;
+mod pin;
 pub mod prelude;
 pub mod m95320;
+#[cfg(feature = "sequential-storage")]
+pub mod sequential;
+#[cfg(feature = "sim")]
+pub mod sim;
+#[cfg(feature = "spi-memory")]
+mod spi_memory_compat;
+#[cfg(feature = "embedded-storage")]
+mod storage;
 mod utils;
 
+pub use crate::delay::NoDelay;
 pub use crate::error::Error;
+pub use crate::pin::NoPin;
 
-use embedded_hal::blocking::spi::Transfer;
-use embedded_hal::digital::v2::OutputPin;
+/// A trait for reading operations from a memory device.
+///
+/// It doesn't depend on how the device is connected, so storage code can be
+/// written over any backend, eg. an EEPROM, a RAM buffer or a cache layer.
+pub trait Read<Addr> {
+    /// The error returned by the device.
+    type Error;
 
-/// A trait for reading operations from a memory chip.
-pub trait Read<Addr, SPI: Transfer<u8>, CS: OutputPin> {
-    /// Reads bytes from a memory chip.
+    /// Reads bytes from a memory device.
     ///
     /// # Parameters
     /// * `addr`: The address to start reading at.
     /// * `buf`: The buffer to read `buf.len()` bytes into.
-    fn read(&mut self, addr: Addr, buf: &mut [u8]) -> Result<(), Error<SPI, CS>>;
+    fn read(&mut self, addr: Addr, buf: &mut [u8]) -> Result<(), Self::Error>;
+
+    /// Returns the size of the device in bytes.
+    fn capacity(&self) -> usize;
 }
 
-/// A trait for writing and erasing operations on a memory chip.
-pub trait BlockDevice<Addr, SPI: Transfer<u8>, CS: OutputPin> {
-    /// Erases sectors from the memory chip.
+/// A trait for writing and erasing operations on a memory device.
+pub trait BlockDevice<Addr>: Read<Addr> {
+    /// Returns the size of the sectors erased by [`BlockDevice::erase_sectors`]
+    /// in bytes.
+    fn page_size(&self) -> usize;
+
+    /// Erases sectors from the memory device.
     ///
     /// # Parameters
-    /// * `addr`: The address to start erasing at. If the address is not on a sector boundary,
-    ///   the lower bits can be ignored in order to make it fit.
-    fn erase_sectors(&mut self, addr: Addr, amount: usize) -> Result<(), Error<SPI, CS>>;
+    /// * `addr`: The address to start erasing at. It has to be on a sector boundary, a multiple
+    ///   of [`BlockDevice::page_size`], or the erase fails without changing the device.
+    /// * `amount`: The number of sectors to erase.
+    fn erase_sectors(&mut self, addr: Addr, amount: usize) -> Result<(), Self::Error>;
 
-    /// Erases the memory chip fully.
+    /// Erases the memory device fully.
     ///
     /// Warning: Full erase operations can take a significant amount of time.
     /// Check your device's datasheet for precise numbers.
-    fn erase_all(&mut self) -> Result<(), Error<SPI, CS>>;
+    fn erase_all(&mut self) -> Result<(), Self::Error>;
 
-    /// Writes bytes onto the memory chip. This method is supposed to assume that the sectors
+    /// Writes bytes onto the memory device. This method is supposed to assume that the sectors
     /// it is writing to have already been erased and should not do any erasing themselves.
     ///
     /// # Parameters
     /// * `addr`: The address to write to.
     /// * `data`: The bytes to write to `addr`.
-    fn write_bytes(&mut self, addr: Addr, data: &mut [u8]) -> Result<(), Error<SPI, CS>>;
+    fn write_bytes(&mut self, addr: Addr, data: &[u8]) -> Result<(), Self::Error>;
 }
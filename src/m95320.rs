@@ -1,19 +1,85 @@
-use crate::{ BlockDevice, Error, Read };
+use crate::{ BlockDevice, Error, NoDelay, NoPin, Read };
+use crate::chip::{Chip, M95320};
+use crate::utils::HexSlice;
+
+use core::convert::TryFrom;
+use core::ops::Range;
 
 use bitflags::bitflags;
 
-use embedded_hal::blocking::spi::Transfer;
-use embedded_hal::digital::v2::OutputPin;
+use embedded_hal::delay::DelayNs;
+use embedded_hal::digital::OutputPin;
+use embedded_hal::spi::{Operation, SpiDevice};
+
+/// Address bit that selects the lock status instead of the Identification Page
+/// for the `ReadIdentificationPage` and `WriteIdentificationPage` opcodes.
+pub(crate) const ID_LOCK_ADDRESS: u32 = 1 << 10;
+/// Bit of the lock status byte that is set when the Identification Page is locked.
+pub(crate) const ID_LOCK_BIT: u8 = 1 << 1;
+/// Default time to wait between two status register polls while a write is in
+/// progress.
+pub(crate) const DEFAULT_POLL_INTERVAL_US: u32 = 100;
+
+/// Maximum number of data transfers in one transaction when transfers are
+/// limited by [`Flash::set_max_transfer_size`]. Longer reads need a chip select
+/// pin, and longer writes continue with a new `WRITE` instruction.
+const MAX_TRANSFERS: usize = 16;
+
+/// Value of erased bytes, unless changed with [`Flash::set_erase_fill`].
+pub(crate) const DEFAULT_ERASE_FILL: u8 = 0xFF;
+
+/// Bits 4 to 6 of the status register, which read as 0 on every supported
+/// chip. A floating `Q` line reads them as 1.
+const STATUS_RESERVED_BITS: u8 = 0b0111_0000;
+
+/// Returns the default write timeout for chip `C`: tW max plus 50%.
+pub(crate) fn default_write_timeout_us<C: Chip>() -> u32 {
+    C::WRITE_CYCLE_TIME_US + C::WRITE_CYCLE_TIME_US / 2
+}
 
-const PAGE_SIZE: u16 = 32;
-enum Opcode {
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum Opcode {
     WriteEnable = 0x06,
     WriteDisable = 0x04,
     ReadStatusRegister = 0x05,
-    #[allow(dead_code)]
     WriteStatusRegister = 0x01,
     Read = 0x03,
     Write = 0x02,
+    ReadIdentificationPage = 0x83,
+    WriteIdentificationPage = 0x82,
+}
+
+impl Opcode {
+    #[cfg_attr(not(feature = "sim"), allow(dead_code))]
+    pub(crate) fn from_byte(byte: u8) -> Option<Self> {
+        match byte {
+            0x06 => Some(Opcode::WriteEnable),
+            0x04 => Some(Opcode::WriteDisable),
+            0x05 => Some(Opcode::ReadStatusRegister),
+            0x01 => Some(Opcode::WriteStatusRegister),
+            0x03 => Some(Opcode::Read),
+            0x02 => Some(Opcode::Write),
+            0x83 => Some(Opcode::ReadIdentificationPage),
+            0x82 => Some(Opcode::WriteIdentificationPage),
+            _ => None,
+        }
+    }
+}
+
+/// Encodes `opcode` followed by `addr` the way chip `C` expects it.
+///
+/// Returns the buffer and the number of bytes of it that are used.
+pub(crate) fn address_command<C: Chip>(opcode: Opcode, addr: u32) -> ([u8; 4], usize) {
+    let address_bytes = usize::from(C::ADDRESS_BYTES);
+    let mut buf = [opcode as u8, 0, 0, 0];
+    for (i, byte) in buf[1..=address_bytes].iter_mut().enumerate() {
+        *byte = (addr >> (8 * (address_bytes - 1 - i))) as u8;
+    }
+    if address_bytes == 1 && addr > 0xFF {
+        // The ninth address bit is sent as bit 3 of the instruction
+        buf[0] |= 0b0000_1000;
+    }
+    (buf, 1 + address_bytes)
 }
 
 bitflags! {
@@ -30,28 +96,628 @@ bitflags! {
     }
 }
 
-/// Driver for M95320 SPI Flash chips.
+impl Status {
+    /// Bit 7 of the status register under the name used by Microchip, Atmel
+    /// and onsemi. It works like the SRWD bit with the `W` pin.
+    pub const WRITE_PROTECT_ENABLE: Status = Status::STATUS_REGISTER_WRITE_DISABLE;
+
+    /// Parses a status register value read from chip `C`.
+    ///
+    /// Bits the chip doesn't define are dropped, and only `WIP` is kept while
+    /// a write is in progress if the other bits are unreliable then.
+    pub fn from_chip<C: Chip>(bits: u8) -> Status {
+        let status = Status::from_bits_truncate(bits);
+        if !C::STATUS_VALID_WHILE_BUSY && status.contains(Status::WRITE_IN_PROGRESS) {
+            return Status::WRITE_IN_PROGRESS;
+        }
+        status
+    }
+
+    /// Returns the area selected by the `BP1`/`BP0` bits.
+    pub fn protected_area(&self) -> ProtectedArea {
+        match (*self & Status::BLOCK_PROTECT).bits() >> 2 {
+            0b00 => ProtectedArea::None,
+            0b01 => ProtectedArea::UpperQuarter,
+            0b10 => ProtectedArea::UpperHalf,
+            _ => ProtectedArea::All,
+        }
+    }
+}
+
+/// Area of the memory array that is write protected by the `BP1`/`BP0` bits of
+/// the status register.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ProtectedArea {
+    /// No protection.
+    None,
+    /// The upper quarter is protected, addresses `0x0C00` to `0x0FFF` on the
+    /// M95320.
+    UpperQuarter,
+    /// The upper half is protected, addresses `0x0800` to `0x0FFF` on the
+    /// M95320.
+    UpperHalf,
+    /// The whole memory array is protected.
+    All,
+}
+
+impl ProtectedArea {
+    /// Returns the protected addresses of chip `C`. The range is empty for
+    /// [`ProtectedArea::None`].
+    pub fn address_range<C: Chip>(&self) -> Range<u32> {
+        let start = match self {
+            ProtectedArea::None => C::CAPACITY,
+            ProtectedArea::UpperQuarter => C::CAPACITY / 4 * 3,
+            ProtectedArea::UpperHalf => C::CAPACITY / 2,
+            ProtectedArea::All => 0,
+        };
+        start..C::CAPACITY
+    }
+
+    /// Returns whether any byte in `addr..addr + len` of chip `C` is protected.
+    pub fn overlaps<C: Chip>(&self, addr: u32, len: usize) -> bool {
+        let range = self.address_range::<C>();
+        len > 0 && u64::from(addr) + len as u64 > u64::from(range.start) && addr < range.end
+    }
+
+    fn bits(&self) -> Status {
+        let bits = match self {
+            ProtectedArea::None => 0b00,
+            ProtectedArea::UpperQuarter => 0b01,
+            ProtectedArea::UpperHalf => 0b10,
+            ProtectedArea::All => 0b11,
+        };
+        Status::from_bits_truncate(bits << 2)
+    }
+}
+
+/// Region of the memory array that identifies a card, and a hash of its
+/// contents. Set with [`Flash::set_fingerprint_region`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct Fingerprint {
+    addr: u32,
+    len: usize,
+    hash: u32,
+}
+
+/// Confirmation that the Identification Page should be locked, which can't be
+/// undone. Required by [`Flash::lock_id_page`].
+#[derive(Debug)]
+pub struct PermanentLock(());
+
+impl PermanentLock {
+    /// Confirms that locking the Identification Page makes it read-only for the
+    /// lifetime of the chip.
+    pub fn i_understand_this_cannot_be_undone() -> Self {
+        PermanentLock(())
+    }
+}
+
+/// Control pins of the chip that are driven by the driver.
 ///
-/// Implementation is not complete. Missing ability to write to status registers,
-/// write-protect, hold functionality, and soft reset
+/// Pins left at `None` are not connected to the driver: `W` and `HOLD` are
+/// assumed to be tied high, and chip select is assumed to be driven by the
+/// [`SpiDevice`].
+#[derive(Debug)]
+pub struct Pins<PIN> {
+    /// The `W` (write protect) pin.
+    pub write_protect: Option<PIN>,
+    /// The `HOLD` pin.
+    pub hold: Option<PIN>,
+    /// The `S` (chip select) pin, for an [`SpiDevice`] that does not drive chip
+    /// select itself, eg. one that shares the bus with a no-op chip select pin.
+    /// Needed to keep a [`HeldRead`] open across transactions.
+    pub chip_select: Option<PIN>,
+}
+
+impl<PIN> Default for Pins<PIN> {
+    fn default() -> Self {
+        Self { write_protect: None, hold: None, chip_select: None }
+    }
+}
+
+/// How the status register is polled while waiting for a write cycle to finish.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PollMode {
+    /// Every poll is a separate `RDSR` instruction, selecting and deselecting
+    /// the chip. This is the default.
+    Command,
+    /// A single `RDSR` instruction is kept open and status bytes are read until
+    /// `WIP` clears, so chip-select only toggles once per write cycle.
+    ///
+    /// Needs the chip select pin to be driven by the driver, see [`Pins`].
+    Continuous,
+}
+
+/// What happens to an access that runs past the end of the memory array.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum WrapPolicy {
+    /// The access fails with [`Error::AddressOutOfBounds`] before anything is
+    /// sent to the chip. This is the default.
+    Reject,
+    /// The access continues at address 0, like the chip does by itself for
+    /// reads. It still has to start inside the memory array, and can't be longer
+    /// than it.
+    Wrap,
+}
+
+/// Returns whether `len` bytes starting at `addr` can be accessed on chip `C`.
+pub(crate) fn in_bounds<C: Chip>(addr: u32, len: usize, wrap_policy: WrapPolicy) -> bool {
+    let capacity = u64::from(C::CAPACITY);
+    match wrap_policy {
+        WrapPolicy::Reject => u64::from(addr) + len as u64 <= capacity,
+        WrapPolicy::Wrap => u64::from(addr) < capacity && len as u64 <= capacity,
+    }
+}
+
+/// Driver for M95320 SPI Flash chips, and the rest of the M95xxx family.
+///
+/// Implementation is not complete. Missing soft reset
 /// 
 /// # Type Parameters
 ///
-/// * **`SPI`**: The SPI master to which the flash chip is attached.
-/// * **`CS`**: The **C**hip-**S**elect line attached to the `\CS`/`\CE` pin of
-///   the flash chip.
+/// * **`SPI`**: The SPI device the flash chip is attached to. Unless a chip
+///   select pin is passed in [`Pins`], chip-select is handled by the device and
+///   every command is sent as a single transaction.
+/// * **`PIN`**: The type of the optional control [`Pins`]. Defaults to
+///   [`NoPin`] when none are driven by the driver.
+/// * **`C`**: The [`Chip`] descriptor of the part. Defaults to the [`M95320`].
+/// * **`D`**: The delay provider used while waiting for write cycles, see
+///   [`Flash::with_delay`]. Defaults to [`NoDelay`] when there is none.
 #[derive(Debug)]
-pub struct Flash<SPI: Transfer<u8>, CS: OutputPin> {
+pub struct Flash<SPI: SpiDevice, PIN: OutputPin = NoPin, C: Chip = M95320, D: DelayNs = NoDelay> {
     spi: SPI,
-    cs: CS,
+    chip: C,
+    /// Waits between status register polls, if a provider was given.
+    delay: Option<D>,
+    /// The `W` pin, if it is driven by the driver.
+    write_protect: Option<PIN>,
+    /// Whether `W` is driven low.
+    write_protect_asserted: bool,
+    /// The `HOLD` pin, if it is driven by the driver.
+    hold: Option<PIN>,
+    /// The `S` pin, if it is driven by the driver.
+    chip_select: Option<PIN>,
+    /// Settings and the state of the chip.
+    state: State,
+}
+
+/// Settings of a [`Flash`] and what it knows about the state of the chip,
+/// apart from the bus and pins. Kept for each slot of a
+/// [`FlashArray`](crate::array::FlashArray).
+#[derive(Debug, Clone, Copy)]
+pub(crate) struct State {
+    /// Time to wait between two status register polls.
+    poll_interval_us: u32,
+    /// Time after which a write cycle is abandoned.
+    write_timeout_us: u32,
+    /// Duration of the last write cycle, if it was measured.
+    last_write_cycle_us: Option<u32>,
+    /// How the status register is polled during write cycles.
+    poll_mode: PollMode,
+    /// Maximum length of a single data transfer.
+    max_transfer_size: usize,
+    /// Maximum length of a transaction, including the instruction and address.
+    max_transaction_size: usize,
+    /// Whether accesses may run past the end of the memory array.
+    wrap_policy: WrapPolicy,
+    /// Value written to erased bytes.
+    erase_fill: u8,
+    /// Whether a write cycle was started and not seen to finish yet.
+    write_pending: bool,
+    /// Block protection as of the last status register read or write.
+    protected: ProtectedArea,
+    /// Identifies the inserted card, if a region was set.
+    fingerprint: Option<Fingerprint>,
+}
+
+impl State {
+    /// Returns the default settings for chip `C`, before anything is known
+    /// about the chip.
+    pub(crate) fn new<C: Chip>() -> Self {
+        Self {
+            poll_interval_us: DEFAULT_POLL_INTERVAL_US,
+            write_timeout_us: default_write_timeout_us::<C>(),
+            last_write_cycle_us: None,
+            poll_mode: PollMode::Command,
+            max_transfer_size: usize::MAX,
+            max_transaction_size: usize::MAX,
+            wrap_policy: WrapPolicy::Reject,
+            erase_fill: DEFAULT_ERASE_FILL,
+            write_pending: false,
+            protected: ProtectedArea::None,
+            fingerprint: None,
+        }
+    }
+}
+
+impl<SPI: SpiDevice> Flash<SPI> {
+    /// Initializes the driver for a chip whose control pins are not driven by
+    /// the driver.
+    ///
+    /// `W` and `HOLD` are assumed to be tied high.
+    pub fn init(spi: SPI) -> Result<Self, Error<SPI>> {
+        Self::init_with_chip_and_pins(spi, M95320, Pins::default())
+    }
 }
 
-impl<SPI: Transfer<u8>, CS: OutputPin> Flash<SPI, CS> {
-    pub fn init(spi: SPI, cs: CS) -> Result<Self, Error<SPI, CS>> {
-        let mut this = Self { spi, cs };
-        this.cs.set_high().map_err(Error::Gpio)?;
-        let status = this.read_status()?;
-        info!("Flash::init: status = {:?}", status);
+impl<SPI: SpiDevice, C: Chip> Flash<SPI, NoPin, C> {
+    /// Initializes the driver for another chip of the family, whose control pins
+    /// are not driven by the driver.
+    pub fn init_with_chip(spi: SPI, chip: C) -> Result<Self, Error<SPI>> {
+        Self::init_with_chip_and_pins(spi, chip, Pins::default())
+    }
+}
+
+impl<SPI: SpiDevice, PIN: OutputPin> Flash<SPI, PIN> {
+    /// Initializes the driver and takes control of the `W` pin of the chip,
+    /// driving it high so the status register can be written.
+    pub fn init_with_write_protect(spi: SPI, write_protect: PIN) -> Result<Self, Error<SPI, PIN>> {
+        Self::init_with_pins(spi, Pins { write_protect: Some(write_protect), ..Pins::default() })
+    }
+
+    /// Initializes the driver and takes control of the given pins of the chip,
+    /// driving `W` and `HOLD` high and deselecting the chip.
+    pub fn init_with_pins(spi: SPI, pins: Pins<PIN>) -> Result<Self, Error<SPI, PIN>> {
+        Self::init_with_chip_and_pins(spi, M95320, pins)
+    }
+}
+
+impl<SPI: SpiDevice, PIN: OutputPin, C: Chip> Flash<SPI, PIN, C> {
+    /// Initializes the driver for another chip of the family, and takes control
+    /// of the given pins like [`Flash::init_with_pins`].
+    pub fn init_with_chip_and_pins(spi: SPI, chip: C, pins: Pins<PIN>) -> Result<Self, Error<SPI, PIN>> {
+        let mut this = Self::with_state(spi, chip, pins, None, State::new::<C>());
+        this.attach()?;
+        Ok(this)
+    }
+
+    /// Sets the delay provider used while waiting for write cycles to finish.
+    ///
+    /// With it, the status register is polled every
+    /// [poll interval](Flash::set_poll_interval_us) and a write cycle that
+    /// takes longer than the [write timeout](Flash::set_write_timeout_us) fails
+    /// with [`Error::Timeout`]. Without it, polling never gives up.
+    pub fn with_delay<D: DelayNs>(self, delay: D) -> Flash<SPI, PIN, C, D> {
+        Flash {
+            spi: self.spi,
+            chip: self.chip,
+            delay: Some(delay),
+            write_protect: self.write_protect,
+            write_protect_asserted: self.write_protect_asserted,
+            hold: self.hold,
+            chip_select: self.chip_select,
+            state: self.state,
+        }
+    }
+}
+
+impl<SPI: SpiDevice, PIN: OutputPin, C: Chip, D: DelayNs> Flash<SPI, PIN, C, D> {
+    /// Creates the driver with the given settings and state, without talking
+    /// to the chip.
+    pub(crate) fn with_state(spi: SPI, chip: C, pins: Pins<PIN>, delay: Option<D>, state: State) -> Self {
+        Self {
+            spi,
+            chip,
+            delay,
+            write_protect: pins.write_protect,
+            write_protect_asserted: false,
+            hold: pins.hold,
+            chip_select: pins.chip_select,
+            state,
+        }
+    }
+
+    /// Returns the settings and state, to create the driver again later with
+    /// [`Flash::with_state`].
+    pub(crate) fn state(&self) -> State {
+        self.state
+    }
+
+    fn select(&mut self) -> Result<(), Error<SPI, PIN>> {
+        match self.chip_select.as_mut() {
+            Some(cs) => cs.set_low().map_err(Error::Gpio),
+            None => Ok(()),
+        }
+    }
+
+    fn deselect(&mut self) -> Result<(), Error<SPI, PIN>> {
+        match self.chip_select.as_mut() {
+            Some(cs) => cs.set_high().map_err(Error::Gpio),
+            None => Ok(()),
+        }
+    }
+
+    /// Sends a command, after waiting for a pending write cycle to finish.
+    fn command(&mut self, operations: &mut [Operation<'_, u8>]) -> Result<(), Error<SPI, PIN>> {
+        self.flush()?;
+        self.transaction(operations)
+    }
+
+    fn transaction(&mut self, operations: &mut [Operation<'_, u8>]) -> Result<(), Error<SPI, PIN>> {
+        // If the SPI transaction fails, make sure to disable CS anyways
+        self.select()?;
+        let spi_result = self.spi.transaction(operations).map_err(Error::Spi);
+        self.deselect()?;
+        spi_result
+    }
+
+    /// Starts a `READ` at `addr` that is kept open until the returned
+    /// [`HeldRead`] is finished or dropped.
+    ///
+    /// The data can then be read in chunks of any size without sending a new
+    /// `READ` instruction, and the transfer can be paused with the `HOLD` pin
+    /// to let another device use the bus in between.
+    ///
+    /// Fails with [`Error::MissingPin`] unless the driver was initialized with
+    /// a chip select pin, and with [`Error::AddressOutOfBounds`] if `addr` is
+    /// not in the memory array.
+    pub fn start_read(&mut self, addr: u32) -> Result<HeldRead<'_, SPI, PIN, C, D>, Error<SPI, PIN>> {
+        if self.chip_select.is_none() {
+            return Err(Error::MissingPin);
+        }
+        if addr >= C::CAPACITY {
+            return Err(Error::AddressOutOfBounds { start: addr, len: 0 });
+        }
+        self.flush()?;
+
+        let (cmd_buf, cmd_len) = address_command::<C>(Opcode::Read, addr);
+
+        self.select()?;
+        let read = HeldRead { flash: self, addr, held: false, open: true };
+        read.flash.spi.write(&cmd_buf[..cmd_len]).map_err(Error::Spi)?;
+        Ok(read)
+    }
+
+    /// Starts writing `data` at `addr` without waiting for the write cycles to
+    /// finish.
+    ///
+    /// The first page is sent right away, the rest one page at a time by
+    /// [`PendingWrite::poll`], which has to be called until it returns
+    /// `Ok(())`.
+    pub fn start_write<'a>(
+        &'a mut self,
+        addr: u32,
+        data: &'a [u8],
+    ) -> Result<PendingWrite<'a, SPI, PIN, C, D>, Error<SPI, PIN>> {
+        self.check_write(addr, data.len())?;
+
+        let mut write = PendingWrite { flash: self, addr, remaining: data };
+        write.send_next()?;
+        Ok(write)
+    }
+
+    /// Writes the same `data` at `addr` on each of `flashes`, eg. one chip per
+    /// card slot on a shared SPI bus.
+    ///
+    /// Each page is sent to every chip in turn, and a chip's write cycle is
+    /// only waited for before its next page is sent, so the chips write in
+    /// parallel. Returns once all write cycles have finished.
+    ///
+    /// Fails before anything is sent if the range is out of bounds or write
+    /// protected on any of the chips. Other errors stop the write on all of
+    /// them.
+    ///
+    /// Chips that share a bus and are only selected by their own pins can't
+    /// each own it. Put them into a [`FlashArray`](crate::array::FlashArray)
+    /// and use [`FlashArray::write_interleaved`](crate::array::FlashArray::write_interleaved)
+    /// instead.
+    pub fn write_interleaved(flashes: &mut [Self], addr: u32, data: &[u8]) -> Result<(), Error<SPI, PIN>> {
+        write_interleaved::<C, _>(flashes, addr, data)
+    }
+
+    /// Returns the descriptor of the chip.
+    pub fn chip(&self) -> C {
+        self.chip
+    }
+
+    /// Sets the time to wait between two status register polls while a write
+    /// cycle is in progress. Defaults to 100 µs.
+    pub fn set_poll_interval_us(&mut self, interval_us: u32) {
+        self.state.poll_interval_us = interval_us;
+    }
+
+    /// Sets the time after which a write cycle that is still in progress fails
+    /// with [`Error::Timeout`]. Defaults to the maximum write cycle time of the
+    /// chip plus 50%.
+    pub fn set_write_timeout_us(&mut self, timeout_us: u32) {
+        self.state.write_timeout_us = timeout_us;
+    }
+
+    /// Sets how the status register is polled while waiting for a write cycle
+    /// to finish.
+    ///
+    /// Fails with [`Error::MissingPin`] when selecting [`PollMode::Continuous`]
+    /// without a chip select pin.
+    pub fn set_poll_mode(&mut self, mode: PollMode) -> Result<(), Error<SPI, PIN>> {
+        if mode == PollMode::Continuous && self.chip_select.is_none() {
+            return Err(Error::MissingPin);
+        }
+        self.state.poll_mode = mode;
+        Ok(())
+    }
+
+    /// Limits the length of each transfer handed to the SPI device to `size`
+    /// bytes, for drivers that can't transfer a whole buffer at once.
+    ///
+    /// Longer reads and writes are split into several transfers within one
+    /// transaction, as the chip increments the address by itself. Unlimited by
+    /// default. A `size` of 0 is treated as 1.
+    ///
+    /// Up to 16 transfers fit in one transaction. A longer read stays within
+    /// one `READ` instruction by keeping the chip selected through its chip
+    /// select pin between transactions, and fails with
+    /// [`Error::ReadTooLong`] before anything is sent without one. Longer
+    /// writes are split into several `WRITE` instructions.
+    pub fn set_max_transfer_size(&mut self, size: usize) {
+        self.state.max_transfer_size = size.max(1);
+    }
+
+    /// Limits each transaction handed to the SPI device to `size` bytes,
+    /// including the instruction and address, for devices that send a whole
+    /// transaction as one transfer, like a `LegacyDevice` in single-transfer
+    /// mode.
+    ///
+    /// Longer reads and writes are split into several `READ` and `WRITE`
+    /// instructions. Unlimited by default. At least one data byte is sent with
+    /// each instruction.
+    pub fn set_max_transaction_size(&mut self, size: usize) {
+        self.state.max_transaction_size = size;
+    }
+
+    /// Sets what happens to reads, writes and erases that run past the end of
+    /// the memory array. They are rejected by default.
+    pub fn set_wrap_policy(&mut self, policy: WrapPolicy) {
+        self.state.wrap_policy = policy;
+    }
+
+    /// Sets the value that [`BlockDevice::erase_sectors`] and
+    /// [`BlockDevice::erase_all`] write to erased bytes. Defaults to `0xFF`,
+    /// the value of a new chip.
+    pub fn set_erase_fill(&mut self, fill: u8) {
+        self.state.erase_fill = fill;
+    }
+
+    /// Fails with [`Error::AddressOutOfBounds`] if `len` bytes starting at
+    /// `addr` can't be accessed under the wrap policy.
+    fn check_range(&self, addr: u32, len: usize) -> Result<(), Error<SPI, PIN>> {
+        if in_bounds::<C>(addr, len, self.state.wrap_policy) {
+            Ok(())
+        } else {
+            Err(Error::AddressOutOfBounds { start: addr, len })
+        }
+    }
+
+    /// Checks that `len` bytes starting at `addr` can be written, wrapping past
+    /// the end of the memory array as the wrap policy allows.
+    pub(crate) fn check_write(&self, addr: u32, len: usize) -> Result<(), Error<SPI, PIN>> {
+        self.check_range(addr, len)?;
+        let first_length = len.min((C::CAPACITY - addr) as usize);
+        if self.state.protected.overlaps::<C>(addr, first_length) || self.state.protected.overlaps::<C>(0, len - first_length) {
+            return Err(Error::WriteProtected(addr));
+        }
+        Ok(())
+    }
+
+    /// Returns how long the last write cycle took, in steps of the poll
+    /// interval. This is `None` until a write cycle was waited for with a
+    /// delay provider.
+    pub fn last_write_cycle_us(&self) -> Option<u32> {
+        self.state.last_write_cycle_us
+    }
+
+    /// Reads the status register.
+    ///
+    /// Unlike other commands, this doesn't wait for a pending write cycle to
+    /// finish, so `WIP` may be set.
+    pub fn read_status(&mut self) -> Result<Status, Error<SPI, PIN>> {
+        let bits = self.read_status_bits()?;
+        Ok(Status::from_chip::<C>(bits))
+    }
+
+    /// Reads the raw value of the status register.
+    fn read_status_bits(&mut self) -> Result<u8, Error<SPI, PIN>> {
+        let mut buf = [0];
+        self.transaction(&mut [
+            Operation::Write(&[Opcode::ReadStatusRegister as u8]),
+            Operation::Read(&mut buf),
+        ])?;
+
+        if !Status::from_chip::<C>(buf[0]).contains(Status::WRITE_IN_PROGRESS) {
+            self.state.write_pending = false;
+        }
+        Ok(buf[0])
+    }
+
+    /// Returns whether a chip answers on the bus, eg. to detect that a card
+    /// was removed.
+    ///
+    /// A floating `Q` line reads as all ones or all zeroes. All ones sets bits
+    /// of the status register that always read as 0. All zeroes is told apart
+    /// from an idle chip by setting the Write Enable Latch and reading it back.
+    pub fn probe(&mut self) -> Result<bool, Error<SPI, PIN>> {
+        let bits = self.read_status_bits()?;
+        if bits & STATUS_RESERVED_BITS != 0 {
+            // Chips whose status is unreliable while busy may read all ones
+            return Ok(!C::STATUS_VALID_WHILE_BUSY && self.state.write_pending);
+        }
+        if bits != 0 {
+            return Ok(true);
+        }
+
+        self._write_enable()?;
+        let latched = self.read_status()?.contains(Status::WRITE_ENABLE_LATCH);
+        if latched {
+            self._write_disable()?;
+        }
+        Ok(latched)
+    }
+
+    /// Runs the checks of [`Flash::init`] again, after a card was inserted.
+    ///
+    /// Returns whether a different card was inserted, which is told by the
+    /// contents of the [fingerprint region](Flash::set_fingerprint_region).
+    /// Without one, this is always `false`.
+    ///
+    /// A write that is still in progress is waited for first, like
+    /// [`Flash::flush`] does.
+    ///
+    /// Fails with [`Error::UnexpectedStatus`] if no chip answers, or the chip
+    /// is still busy with a write cycle when the
+    /// [write timeout](Flash::set_write_timeout_us) hits.
+    pub fn reattach(&mut self) -> Result<bool, Error<SPI, PIN>> {
+        if self.probe()? {
+            // Let the card finish its write before it is checked like a new one
+            match self.flush() {
+                Ok(()) | Err(Error::Timeout(_)) => {}
+                Err(e) => return Err(e),
+            }
+        }
+        // A write cycle of the previous card is of no concern to this one
+        self.state.write_pending = false;
+        if !self.probe()? {
+            return Err(Error::UnexpectedStatus);
+        }
+        self.attach()?;
+
+        let fingerprint = match self.state.fingerprint {
+            Some(fingerprint) => fingerprint,
+            None => return Ok(false),
+        };
+        let hash = self.hash_region(fingerprint.addr, fingerprint.len)?;
+        self.state.fingerprint = Some(Fingerprint { hash, ..fingerprint });
+        Ok(hash != fingerprint.hash)
+    }
+
+    /// Sets `len` bytes starting at `addr` as the region that identifies the
+    /// inserted card, eg. where a serial number is stored, and remembers its
+    /// contents for [`Flash::reattach`].
+    pub fn set_fingerprint_region(&mut self, addr: u32, len: usize) -> Result<(), Error<SPI, PIN>> {
+        self.check_range(addr, len)?;
+        let hash = self.hash_region(addr, len)?;
+        self.state.fingerprint = Some(Fingerprint { addr, len, hash });
+        Ok(())
+    }
+
+    /// Drives the control pins to their idle levels and checks that the chip
+    /// is ready for commands.
+    fn attach(&mut self) -> Result<(), Error<SPI, PIN>> {
+        for pin in [&mut self.hold, &mut self.chip_select].iter_mut() {
+            if let Some(pin) = pin.as_mut() {
+                pin.set_high().map_err(Error::Gpio)?;
+            }
+        }
+        let asserted = self.write_protect_asserted;
+        if let Some(pin) = self.write_protect.as_mut() {
+            if asserted {
+                pin.set_low().map_err(Error::Gpio)?;
+            } else {
+                pin.set_high().map_err(Error::Gpio)?;
+            }
+        }
+
+        let status = self.read_status()?;
+        info!("Flash::attach: status = {:?}", status);
+        self.state.protected = status.protected_area();
 
         // Here we don't expect any writes to be in progress
         if !(status & (Status::WRITE_IN_PROGRESS)).is_empty() {
@@ -60,158 +726,684 @@ impl<SPI: Transfer<u8>, CS: OutputPin> Flash<SPI, CS> {
 
         if !(status & (Status::WRITE_ENABLE_LATCH)).is_empty() {
             warn!("Write Enable Latch was set on init! Going to assume we're okay and disable it");
-            let result = this._write_disable();
+            let result = self._write_disable();
+            #[allow(clippy::single_match)]
             match result {
                 Err(_) => return Err(Error::UnexpectedStatus),
                 Ok(_) => (),
             };
         }
 
-        Ok(this)
+        Ok(())
     }
 
-    fn command(&mut self, bytes: &mut [u8]) -> Result<(), Error<SPI, CS>> {
-        // If the SPI transfer fails, make sure to disable CS anyways
-        self.cs.set_low().map_err(Error::Gpio)?;
-        let spi_result = self.spi.transfer(bytes).map_err(Error::Spi);
-        self.cs.set_high().map_err(Error::Gpio)?;
-        spi_result?;
+    /// Returns the FNV-1a hash of `len` bytes starting at `addr`.
+    fn hash_region(&mut self, addr: u32, len: usize) -> Result<u32, Error<SPI, PIN>> {
+        let mut hash: u32 = 0x811c_9dc5;
+        let mut buf = [0; 32];
+        let mut current_addr = addr;
+        let mut remaining = len;
+        while remaining > 0 {
+            let chunk = &mut buf[..remaining.min(32)];
+            self.read_chunked(current_addr, chunk)?;
+            for byte in chunk.iter() {
+                hash = (hash ^ u32::from(*byte)).wrapping_mul(0x0100_0193);
+            }
+            current_addr = ((u64::from(current_addr) + chunk.len() as u64) % u64::from(C::CAPACITY)) as u32;
+            remaining -= chunk.len();
+        }
+        Ok(hash)
+    }
+
+    /// Waits for the last write cycle to finish, if it is still in progress.
+    ///
+    /// Writes return as soon as their last page is sent to the chip, and the
+    /// driver only waits for the write cycle before the next command. Call this
+    /// to make sure the data is stored, eg. before the chip is powered down.
+    pub fn flush(&mut self) -> Result<(), Error<SPI, PIN>> {
+        if self.state.write_pending {
+            self.wait_done()?;
+            self.state.write_pending = false;
+        }
         Ok(())
     }
 
-    /// Reads the status register.
-    pub fn read_status(&mut self) -> Result<Status, Error<SPI, CS>> {
-        let mut buf = [Opcode::ReadStatusRegister as u8, 0];
-        self.command(&mut buf)?;
+    /// Sets the Write Enable Latch, you probably don't need to be using this command, it's used internally before write commands
+    pub fn _write_enable(&mut self) -> Result<(), Error<SPI, PIN>> {
+        self.command(&mut [Operation::Write(&[Opcode::WriteEnable as u8])])
+    }
 
-        Ok(Status::from_bits_truncate(buf[1]))
+    /// Unsets the Write Enable Latch, you probably don't need to be using this command, it undoes the _write_enable() method
+    pub fn _write_disable(&mut self) -> Result<(), Error<SPI, PIN>> {
+        self.command(&mut [Operation::Write(&[Opcode::WriteDisable as u8])])
     }
 
-    /// Sets the Write Enable Latch, you probably don't need to be using this command, it's used internally before write commands
-    pub fn _write_enable(&mut self) -> Result<(), Error<SPI, CS>> {
-        let mut cmd_buf = [Opcode::WriteEnable as u8];
-        self.command(&mut cmd_buf)?;
+    /// Returns the write protected area, as set by the `BP1`/`BP0` bits of the
+    /// status register.
+    pub fn protected_area(&mut self) -> Result<ProtectedArea, Error<SPI, PIN>> {
+        self.flush()?;
+        self.state.protected = self.read_status()?.protected_area();
+        Ok(self.state.protected)
+    }
+
+    /// Sets the `BP1`/`BP0` bits of the status register to write protect `area`.
+    ///
+    /// Writes that touch the protected area fail with [`Error::WriteProtected`]
+    /// before anything is sent to the chip.
+    ///
+    /// Fails with [`Error::UnexpectedStatus`] if the chip ignored the new value,
+    /// which happens when the status register is locked by the `SRWD` bit and
+    /// the `W` pin.
+    pub fn set_protection(&mut self, area: ProtectedArea) -> Result<(), Error<SPI, PIN>> {
+        // The other bits may read as 0 while a write cycle is in progress
+        self.flush()?;
+        let status = self.read_status()?;
+        let value = (status & Status::STATUS_REGISTER_WRITE_DISABLE) | area.bits();
+        self.write_status(value)?;
+
+        self.state.protected = self.read_status()?.protected_area();
+        if self.state.protected != area {
+            return Err(Error::UnexpectedStatus);
+        }
         Ok(())
     }
 
-    /// Unsets the Write Enable Latch, you probably don't need to be using this command, it undoes the _write_enable() method
-    pub fn _write_disable(&mut self) -> Result<(), Error<SPI, CS>> {
-        let mut cmd_buf = [Opcode::WriteDisable as u8];
-        self.command(&mut cmd_buf)?;
+    /// Drives the `W` pin low if `asserted`, or high otherwise.
+    ///
+    /// While `W` is low and the `SRWD` bit is set, the chip is in Hardware
+    /// Protected Mode and ignores writes to the status register.
+    ///
+    /// Fails with [`Error::MissingPin`] if the driver was initialized without a
+    /// `W` pin.
+    pub fn set_write_protect(&mut self, asserted: bool) -> Result<(), Error<SPI, PIN>> {
+        let pin = self.write_protect.as_mut().ok_or(Error::MissingPin)?;
+        if asserted {
+            pin.set_low().map_err(Error::Gpio)?;
+        } else {
+            pin.set_high().map_err(Error::Gpio)?;
+        }
+        self.write_protect_asserted = asserted;
+        Ok(())
+    }
+
+    /// Sets or clears the `SRWD` bit of the status register, keeping the block
+    /// protect bits.
+    ///
+    /// Fails with [`Error::UnexpectedStatus`] if the chip ignored the new value
+    /// because it is in Hardware Protected Mode.
+    pub fn set_status_register_write_disable(&mut self, disable: bool) -> Result<(), Error<SPI, PIN>> {
+        self.flush()?;
+        let mut value = self.read_status()? & Status::BLOCK_PROTECT;
+        value.set(Status::STATUS_REGISTER_WRITE_DISABLE, disable);
+        self.write_status(value)?;
+
+        if self.read_status()?.contains(Status::STATUS_REGISTER_WRITE_DISABLE) != disable {
+            return Err(Error::UnexpectedStatus);
+        }
+        Ok(())
+    }
+
+    /// Returns whether the chip is in Hardware Protected Mode, where the status
+    /// register can't be written: the `SRWD` bit is set and `W` is driven low.
+    ///
+    /// Without a `W` pin this is always `false`, since `W` is assumed to be
+    /// tied high.
+    pub fn is_hardware_protected(&mut self) -> Result<bool, Error<SPI, PIN>> {
+        self.flush()?;
+        let status = self.read_status()?;
+        Ok(self.write_protect_asserted && status.contains(Status::STATUS_REGISTER_WRITE_DISABLE))
+    }
+
+    /// Locks the status register by setting the `SRWD` bit and driving `W` low,
+    /// entering Hardware Protected Mode. The block protection set with
+    /// [`Flash::set_protection`] can't be changed until
+    /// [`Flash::unlock_status_register`] is called.
+    pub fn lock_status_register(&mut self) -> Result<(), Error<SPI, PIN>> {
+        if self.write_protect.is_none() {
+            return Err(Error::MissingPin);
+        }
+        self.set_status_register_write_disable(true)?;
+        self.set_write_protect(true)
+    }
+
+    /// Leaves Hardware Protected Mode by driving `W` high and clearing the `SRWD`
+    /// bit.
+    pub fn unlock_status_register(&mut self) -> Result<(), Error<SPI, PIN>> {
+        self.set_write_protect(false)?;
+        self.set_status_register_write_disable(false)
+    }
+
+    /// Reads `buf.len()` bytes of the Identification Page of an M95xxx-D,
+    /// starting at `offset`. The Identification Page is one page long.
+    pub fn read_id_page(&mut self, offset: u16, buf: &mut [u8]) -> Result<(), Error<SPI, PIN>> {
+        if usize::from(offset) + buf.len() > usize::from(C::PAGE_SIZE) {
+            return Err(Error::AddressOutOfBounds { start: offset.into(), len: buf.len() });
+        }
+
+        let (cmd_buf, cmd_len) = address_command::<C>(Opcode::ReadIdentificationPage, offset.into());
+        self.command(&mut [Operation::Write(&cmd_buf[..cmd_len]), Operation::Read(buf)])?;
+
+        trace!("read_id_page {:#04x}: {:?}", offset, HexSlice(&*buf));
+        Ok(())
+    }
+
+    /// Writes `data` to the Identification Page of an M95xxx-D, starting at
+    /// `offset`.
+    ///
+    /// Fails with [`Error::IdPageLocked`] before anything is written if the
+    /// page has been locked.
+    pub fn write_id_page(&mut self, offset: u16, data: &[u8]) -> Result<(), Error<SPI, PIN>> {
+        if usize::from(offset) + data.len() > usize::from(C::PAGE_SIZE) {
+            return Err(Error::AddressOutOfBounds { start: offset.into(), len: data.len() });
+        }
+        if self.id_page_locked()? {
+            return Err(Error::IdPageLocked);
+        }
+
+        self._write_enable()?;
+
+        let (cmd_buf, cmd_len) = address_command::<C>(Opcode::WriteIdentificationPage, offset.into());
+        self.command(&mut [Operation::Write(&cmd_buf[..cmd_len]), Operation::Write(data)])?;
+        self.state.write_pending = true;
+
+        Ok(())
+    }
+
+    /// Returns whether the Identification Page of an M95xxx-D is locked.
+    pub fn id_page_locked(&mut self) -> Result<bool, Error<SPI, PIN>> {
+        let (cmd_buf, cmd_len) = address_command::<C>(Opcode::ReadIdentificationPage, ID_LOCK_ADDRESS);
+        let mut buf = [0];
+        self.command(&mut [Operation::Write(&cmd_buf[..cmd_len]), Operation::Read(&mut buf)])?;
+
+        Ok(buf[0] & ID_LOCK_BIT != 0)
+    }
+
+    /// Locks the Identification Page of an M95xxx-D, making it read-only.
+    ///
+    /// **This is permanent**, a locked Identification Page can never be written
+    /// again, which is why a [`PermanentLock`] confirmation has to be passed.
+    pub fn lock_id_page(&mut self, _confirm: PermanentLock) -> Result<(), Error<SPI, PIN>> {
+        self._write_enable()?;
+
+        let (cmd_buf, cmd_len) = address_command::<C>(Opcode::WriteIdentificationPage, ID_LOCK_ADDRESS);
+        self.command(&mut [Operation::Write(&cmd_buf[..cmd_len]), Operation::Write(&[ID_LOCK_BIT])])?;
+        self.state.write_pending = true;
+
+        if !self.id_page_locked()? {
+            return Err(Error::UnexpectedStatus);
+        }
         Ok(())
     }
 
-    fn wait_done(&mut self) -> Result<(), Error<SPI, CS>> {
-        // TODO: Consider changing this to a delay based pattern
-        while self.read_status()?.contains(Status::WRITE_IN_PROGRESS) {}
+    fn write_status(&mut self, status: Status) -> Result<(), Error<SPI, PIN>> {
+        self._write_enable()?;
+        self.command(&mut [Operation::Write(&[Opcode::WriteStatusRegister as u8, status.bits()])])?;
+        self.state.write_pending = true;
+        self.flush()
+    }
+
+    fn wait_done(&mut self) -> Result<(), Error<SPI, PIN>> {
+        match self.state.poll_mode {
+            PollMode::Command => self.poll_status(Self::read_status),
+            PollMode::Continuous => {
+                self.select()?;
+                let result = self.spi.write(&[Opcode::ReadStatusRegister as u8])
+                    .map_err(Error::Spi)
+                    .and_then(|_| self.poll_status(|flash| {
+                        let mut buf = [0];
+                        flash.spi.read(&mut buf).map_err(Error::Spi)?;
+                        Ok(Status::from_chip::<C>(buf[0]))
+                    }));
+                let deselected = self.deselect();
+                result.and(deselected)
+            }
+        }
+    }
+
+    /// Calls `read_status` until the write cycle is over, or the timeout hits.
+    fn poll_status(
+        &mut self,
+        mut read_status: impl FnMut(&mut Self) -> Result<Status, Error<SPI, PIN>>,
+    ) -> Result<(), Error<SPI, PIN>> {
+        let mut elapsed_us = 0;
+        while read_status(self)?.contains(Status::WRITE_IN_PROGRESS) {
+            if let Some(delay) = self.delay.as_mut() {
+                if elapsed_us >= self.state.write_timeout_us {
+                    warn!("write cycle did not finish within {} us", elapsed_us);
+                    return Err(Error::Timeout(elapsed_us));
+                }
+                delay.delay_us(self.state.poll_interval_us);
+                elapsed_us = elapsed_us.saturating_add(self.state.poll_interval_us);
+            }
+        }
+
+        if self.delay.is_some() {
+            self.state.last_write_cycle_us = Some(elapsed_us);
+        }
         Ok(())
     }
 
-    fn write_bytes_to_page(&mut self, addr: u16, data: &mut [u8]) -> Result<(), Error<SPI, CS>> {
-        if addr > 2u16.pow(12)-1 {
-            return Err(Error::AddressOutOfBounds(addr.into()))
+    /// Starts the write cycle of `data` to a single page at `addr`, without
+    /// waiting for it to finish.
+    pub(crate) fn send_page(&mut self, addr: u32, data: &[u8]) -> Result<(), Error<SPI, PIN>> {
+        if !in_bounds::<C>(addr, data.len(), WrapPolicy::Reject) {
+            return Err(Error::AddressOutOfBounds { start: addr, len: data.len() })
         }
 
         self._write_enable()?;
 
-        let mut cmd_buf = [
-            Opcode::Write as u8,
-            (addr >> 8) as u8,
-            addr as u8,
-        ];
+        let (cmd_buf, cmd_len) = address_command::<C>(Opcode::Write, addr);
+        let mut chunks = data.chunks(self.state.max_transfer_size);
+        let mut operations: [Operation<'_, u8>; MAX_TRANSFERS + 1] = core::array::from_fn(|i| match i {
+            0 => Operation::Write(&cmd_buf[..cmd_len]),
+            _ => Operation::Write(chunks.next().unwrap_or(&[])),
+        });
+        let transfers = data.len().div_ceil(self.state.max_transfer_size);
+
+        self.command(&mut operations[..=transfers])?;
+        self.state.write_pending = true;
+        Ok(())
+    }
+
+    /// Returns how many of the next `len` bytes to write at `addr` can be sent
+    /// at once: up to the end of the page, or as much as fits in one
+    /// transaction.
+    pub(crate) fn chunk_length(&self, addr: u32, len: usize) -> usize {
+        let page_size = u32::from(C::PAGE_SIZE);
+        ((page_size - addr % page_size) as usize)
+            .min(len)
+            .min(self.max_data_length())
+    }
+
+    /// Returns how many data bytes fit in one transaction after the
+    /// instruction and address.
+    fn max_data_length(&self) -> usize {
+        let command_length = 1 + usize::from(C::ADDRESS_BYTES);
+        self.state.max_transfer_size
+            .saturating_mul(MAX_TRANSFERS)
+            .min(self.state.max_transaction_size.saturating_sub(command_length).max(1))
+    }
+
+    /// Reads `buf.len()` bytes starting at `addr`, in transfers of at most
+    /// `max_transfer_size` bytes and transactions of at most
+    /// `max_transaction_size` bytes.
+    ///
+    /// Each transaction is a single `READ` instruction. When its transfers
+    /// don't fit in one SPI transaction, the chip is kept selected by the chip
+    /// select pin while they are sent.
+    fn read_chunked(&mut self, addr: u32, buf: &mut [u8]) -> Result<(), Error<SPI, PIN>> {
+        let command_length = 1 + usize::from(C::ADDRESS_BYTES);
+        let batch_length = self.state.max_transaction_size.saturating_sub(command_length).max(1);
+        let max_transferred = self.state.max_transfer_size.saturating_mul(MAX_TRANSFERS);
+        if self.chip_select.is_none() && buf.len().min(batch_length) > max_transferred {
+            return Err(Error::ReadTooLong { len: buf.len().min(batch_length), max: max_transferred });
+        }
+
+        let mut current_addr = addr;
+        for batch in buf.chunks_mut(batch_length) {
+            let batch_len = batch.len();
+            let (cmd_buf, cmd_len) = address_command::<C>(Opcode::Read, current_addr);
+
+            if batch_len <= max_transferred {
+                let transfers = batch_len.div_ceil(self.state.max_transfer_size);
+                let mut chunks = batch.chunks_mut(self.state.max_transfer_size);
+                let mut operations: [Operation<'_, u8>; MAX_TRANSFERS + 1] = core::array::from_fn(|i| match i {
+                    0 => Operation::Write(&cmd_buf[..cmd_len]),
+                    _ => Operation::Read(chunks.next().unwrap_or(&mut [])),
+                });
+
+                self.command(&mut operations[..=transfers])?;
+            } else {
+                self.flush()?;
+                self.select()?;
+                let max_transfer_size = self.state.max_transfer_size;
+                let spi = &mut self.spi;
+                let result = spi.write(&cmd_buf[..cmd_len])
+                    .and_then(|_| batch.chunks_mut(max_transfer_size).try_for_each(|chunk| spi.read(chunk)))
+                    .map_err(Error::Spi);
+                let deselected = self.deselect();
+                result.and(deselected)?;
+            }
+
+            current_addr = ((u64::from(current_addr) + batch_len as u64) % u64::from(C::CAPACITY)) as u32;
+        }
+        Ok(())
+    }
+
+    /// Writes `value` to `len` bytes starting at `addr`, continuing at address
+    /// 0 past the end of the memory array as the wrap policy allows.
+    ///
+    /// The whole range is checked before anything is sent, so a range that
+    /// touches the protected area fails without changing the chip.
+    pub(crate) fn fill(&mut self, addr: u32, len: usize, value: u8) -> Result<(), Error<SPI, PIN>> {
+        self.check_write(addr, len)?;
+
+        let filled = [value; 512];
+        let mut current_addr = addr;
+        let mut remaining = len;
+        while remaining > 0 {
+            let chunk_length = remaining.min(filled.len());
+            self.write_wrapping(current_addr, &filled[..chunk_length])?;
+            current_addr = ((u64::from(current_addr) + chunk_length as u64) % u64::from(C::CAPACITY)) as u32;
+            remaining -= chunk_length;
+        }
+
+        Ok(())
+    }
+
+    /// Writes `data` starting at `addr`, continuing at address 0 past the end
+    /// of the memory array. The range has to be checked by the caller.
+    fn write_wrapping(&mut self, addr: u32, data: &[u8]) -> Result<(), Error<SPI, PIN>> {
+        let first_length = data.len().min((C::CAPACITY - addr) as usize);
+        let (head, tail) = data.split_at(first_length);
+        self.write_pages(addr, head)?;
+        self.write_pages(0, tail)
+    }
+
+    /// Writes `data` starting at `addr`, one page at a time.
+    fn write_pages(&mut self, addr: u32, data: &[u8]) -> Result<(), Error<SPI, PIN>> {
+        if self.state.protected.overlaps::<C>(addr, data.len()) {
+            return Err(Error::WriteProtected(addr));
+        }
 
-        self.cs.set_low().map_err(Error::Gpio)?;
-        let mut spi_result = self.spi.transfer(&mut cmd_buf);
-        if spi_result.is_ok() {
-            spi_result = self.spi.transfer(data);
+        let mut current_addr = addr;
+        let mut rest_of_data = data;
+
+        while !rest_of_data.is_empty() {
+            let chunk_length = self.chunk_length(current_addr, rest_of_data.len());
+            let (chunk_data, rest) = rest_of_data.split_at(chunk_length);
+
+            self.send_page(current_addr, chunk_data)?;
+
+            current_addr += chunk_length as u32;
+            rest_of_data = rest;
         }
-        self.cs.set_high().map_err(Error::Gpio)?;
-        spi_result.map(|_| ()).map_err(Error::Spi)?;
 
-        self.wait_done()?;
         Ok(())
     }
 }
 
-impl<SPI: Transfer<u8>, CS: OutputPin> Read<u16, SPI, CS> for Flash<SPI, CS> {
+impl<SPI: SpiDevice, PIN: OutputPin, C: Chip, D: DelayNs> Read<u32> for Flash<SPI, PIN, C, D> {
+    type Error = Error<SPI, PIN>;
+
     /// # Parameters
     ///
-    /// * `addr`: 16-bit address to start reading at.
+    /// * `addr`: Address to start reading at.
     /// * `buf`: Destination buffer to fill.
-    fn read(&mut self, addr: u16, buf: &mut [u8]) -> Result<(), Error<SPI, CS>> {
-        // TODO what happens if `buf` is empty?
+    fn read(&mut self, addr: u32, buf: &mut [u8]) -> Result<(), Error<SPI, PIN>> {
+        self.check_range(addr, buf.len())?;
 
-        let mut cmd_buf = [
-            Opcode::Read as u8,
-            (addr >> 8) as u8,
-            addr as u8,
-        ];
+        self.read_chunked(addr, buf)?;
 
-        self.cs.set_low().map_err(Error::Gpio)?;
-        let mut spi_result = self.spi.transfer(&mut cmd_buf);
-        if spi_result.is_ok() {
-            spi_result = self.spi.transfer(buf);
-        }
-        self.cs.set_high().map_err(Error::Gpio)?;
-        spi_result.map(|_| ()).map_err(Error::Spi)
+        trace!("read {:#05x}: {:?}", addr, HexSlice(&*buf));
+        Ok(())
+    }
+
+    /// Returns the capacity of the chip, see [`Chip::CAPACITY`].
+    fn capacity(&self) -> usize {
+        C::CAPACITY as usize
     }
 }
 
-impl<SPI: Transfer<u8>, CS: OutputPin> BlockDevice<u16, SPI, CS> for Flash<SPI, CS> {
+impl<SPI: SpiDevice, PIN: OutputPin, C: Chip, D: DelayNs> BlockDevice<u32> for Flash<SPI, PIN, C, D> {
+    /// Returns the page size of the chip, see [`Chip::PAGE_SIZE`].
+    fn page_size(&self) -> usize {
+        C::PAGE_SIZE.into()
+    }
+
+    /// Erases whole pages by writing the [erase fill](Flash::set_erase_fill)
+    /// value to them.
+    ///
     /// # Parameters
     /// 
-    /// * `addr`: address to start erasing at
-    /// * `amount`: number of 32byte pages to erase, including the first partial page
-    fn erase_sectors(&mut self, addr: u16, amount: usize) -> Result<(), Error<SPI, CS>> {
-        let first_chunk_length = PAGE_SIZE - (addr % PAGE_SIZE);
-        let mut buf = [0; 32];
+    /// * `addr`: address of the first page to erase. Fails with
+    ///   [`Error::NotPageAligned`] if it is not on a page boundary.
+    /// * `amount`: number of pages to erase
+    fn erase_sectors(&mut self, addr: u32, amount: usize) -> Result<(), Error<SPI, PIN>> {
+        if addr % u32::from(C::PAGE_SIZE) != 0 {
+            return Err(Error::NotPageAligned(addr));
+        }
+        let len = usize::try_from((amount as u64).saturating_mul(C::PAGE_SIZE.into())).unwrap_or(usize::MAX);
 
-        self.write_bytes(addr, &mut buf[..first_chunk_length.into()])?;
+        self.fill(addr, len, self.state.erase_fill)
+    }
+
+    /// Writes `data` page by page, waiting for each write cycle before the
+    /// next page is sent.
+    ///
+    /// Returns without waiting for the last write cycle, which finishes before
+    /// the next command or on [`Flash::flush`].
+    fn write_bytes(&mut self, addr: u32, data: &[u8]) -> Result<(), Error<SPI, PIN>> {
+        self.check_range(addr, data.len())?;
+        self.write_wrapping(addr, data)
+    }
+
+    /// Erases every page of the chip, see [`Flash::erase_sectors`](BlockDevice::erase_sectors).
+    fn erase_all(&mut self) -> Result<(), Error<SPI, PIN>> {
+        self.erase_sectors(0, (C::CAPACITY / u32::from(C::PAGE_SIZE)) as usize)?;
 
-        let mut current_addr = addr + first_chunk_length;
-        for _ in 1..amount {
-            let mut buf = [0; 32];
-            self.write_bytes(current_addr, &mut buf)?;
-            current_addr += PAGE_SIZE;
-        }
-    
         Ok(())
     }
+}
 
-    fn write_bytes(&mut self, addr: u16, data: &mut [u8]) -> Result<(), Error<SPI, CS>> {
-        let mut current_addr: u16 = addr;
+/// Chips that [`write_interleaved`] writes the same data to, each reached by
+/// its index.
+pub(crate) trait InterleavedChips {
+    type Error;
 
-        let first_chunk: &mut [u8];
-        let mut rest_of_data: &mut [u8] = &mut [];
+    /// Returns the number of chips.
+    fn count(&self) -> usize;
 
-        if (data.len() + addr as usize) < PAGE_SIZE.into() {
-            first_chunk = data;
-        } else {
-            let first_chunk_length = PAGE_SIZE - (current_addr % PAGE_SIZE);
-            (first_chunk, rest_of_data) = data.split_at_mut(first_chunk_length.into());
+    /// Checks that `len` bytes starting at `addr` can be written on chip
+    /// `index`, see [`Flash::check_write`].
+    fn check_write(&mut self, index: usize, addr: u32, len: usize) -> Result<(), Self::Error>;
+
+    /// Returns how many of `len` bytes chip `index` takes in one page write at
+    /// `addr`, see [`Flash::chunk_length`].
+    fn chunk_length(&mut self, index: usize, addr: u32, len: usize) -> usize;
+
+    /// Starts the write cycle of one page on chip `index`, see
+    /// [`Flash::send_page`].
+    fn send_page(&mut self, index: usize, addr: u32, data: &[u8]) -> Result<(), Self::Error>;
+
+    /// Waits for the last write cycle of chip `index`.
+    fn flush(&mut self, index: usize) -> Result<(), Self::Error>;
+}
+
+impl<SPI: SpiDevice, PIN: OutputPin, C: Chip, D: DelayNs> InterleavedChips for [Flash<SPI, PIN, C, D>] {
+    type Error = Error<SPI, PIN>;
+
+    fn count(&self) -> usize {
+        self.len()
+    }
+
+    fn check_write(&mut self, index: usize, addr: u32, len: usize) -> Result<(), Self::Error> {
+        self[index].check_write(addr, len)
+    }
+
+    fn chunk_length(&mut self, index: usize, addr: u32, len: usize) -> usize {
+        self[index].chunk_length(addr, len)
+    }
+
+    fn send_page(&mut self, index: usize, addr: u32, data: &[u8]) -> Result<(), Self::Error> {
+        self[index].send_page(addr, data)
+    }
+
+    fn flush(&mut self, index: usize) -> Result<(), Self::Error> {
+        self[index].flush()
+    }
+}
+
+/// Writes the same `data` at `addr` on each of `chips` of type `C`, sending
+/// each page to every chip in turn, like [`Flash::write_interleaved`].
+pub(crate) fn write_interleaved<C: Chip, I: InterleavedChips + ?Sized>(
+    chips: &mut I,
+    addr: u32,
+    data: &[u8],
+) -> Result<(), I::Error> {
+    for index in 0..chips.count() {
+        chips.check_write(index, addr, data.len())?;
+    }
+
+    let mut current_addr = addr;
+    let mut rest_of_data = data;
+
+    while !rest_of_data.is_empty() {
+        let chunk_length = match (0..chips.count()).map(|index| chips.chunk_length(index, current_addr, rest_of_data.len())).min() {
+            Some(chunk_length) => chunk_length,
+            None => return Ok(()),
+        };
+        let (chunk_data, rest) = rest_of_data.split_at(chunk_length);
+
+        for index in 0..chips.count() {
+            chips.send_page(index, current_addr, chunk_data)?;
         }
 
-        self.write_bytes_to_page(addr, first_chunk)?;
+        current_addr = (current_addr + chunk_length as u32) % C::CAPACITY;
+        rest_of_data = rest;
+    }
 
-        if rest_of_data.len() > 0 {
-            // align address to page boundary
-            let remainder = current_addr % PAGE_SIZE;
-            current_addr = current_addr - remainder + PAGE_SIZE;
+    for index in 0..chips.count() {
+        chips.flush(index)?;
+    }
+    Ok(())
+}
 
-            for chunk_data in rest_of_data.rchunks_mut(PAGE_SIZE.into()).rev() {
-                self.write_bytes_to_page(current_addr, chunk_data)?;
-        
-                // advance to next page
-                current_addr += PAGE_SIZE;
-            }
+/// A write started with [`Flash::start_write`], which sends the remaining pages
+/// as the previous write cycles finish.
+///
+/// Dropping it before [`PendingWrite::poll`] returned `Ok(())` leaves the
+/// remaining pages unwritten.
+#[derive(Debug)]
+pub struct PendingWrite<'a, SPI: SpiDevice, PIN: OutputPin, C: Chip, D: DelayNs> {
+    flash: &'a mut Flash<SPI, PIN, C, D>,
+    /// Address of the next byte to send.
+    addr: u32,
+    /// Data that is not sent yet.
+    remaining: &'a [u8],
+}
+
+impl<SPI: SpiDevice, PIN: OutputPin, C: Chip, D: DelayNs> PendingWrite<'_, SPI, PIN, C, D> {
+    /// Returns the number of bytes that are not sent to the chip yet.
+    pub fn remaining(&self) -> usize {
+        self.remaining.len()
+    }
+
+    /// Checks whether the current write cycle is over, and sends the next page
+    /// if it is.
+    ///
+    /// Returns `Ok(())` once the whole data is written, and
+    /// [`nb::Error::WouldBlock`] until then.
+    pub fn poll(&mut self) -> nb::Result<(), Error<SPI, PIN>> {
+        if self.flash.read_status()?.contains(Status::WRITE_IN_PROGRESS) {
+            return Err(nb::Error::WouldBlock);
         }
-   
+        if self.remaining.is_empty() {
+            return Ok(());
+        }
+
+        self.send_next()?;
+        Err(nb::Error::WouldBlock)
+    }
+
+    /// Sends the next page, if there is data left.
+    fn send_next(&mut self) -> Result<(), Error<SPI, PIN>> {
+        if self.remaining.is_empty() {
+            return Ok(());
+        }
+
+        let chunk_length = self.flash.chunk_length(self.addr, self.remaining.len());
+        let (chunk_data, rest) = self.remaining.split_at(chunk_length);
+        self.flash.send_page(self.addr, chunk_data)?;
+
+        self.addr = (self.addr + chunk_length as u32) % C::CAPACITY;
+        self.remaining = rest;
         Ok(())
     }
+}
 
-    fn erase_all(&mut self) -> Result<(), Error<SPI, CS>> {
-        self.erase_sectors(0, (PAGE_SIZE/125).into())?;
+/// A `READ` instruction that is kept open across several transactions, created
+/// with [`Flash::start_read`].
+///
+/// The chip stays selected until [`HeldRead::finish`] is called or the
+/// `HeldRead` is dropped.
+#[derive(Debug)]
+pub struct HeldRead<'a, SPI: SpiDevice, PIN: OutputPin, C: Chip, D: DelayNs> {
+    flash: &'a mut Flash<SPI, PIN, C, D>,
+    /// Address of the next byte to read.
+    addr: u32,
+    /// Whether `HOLD` is driven low.
+    held: bool,
+    /// Whether the chip is still selected.
+    open: bool,
+}
 
+impl<SPI: SpiDevice, PIN: OutputPin, C: Chip, D: DelayNs> HeldRead<'_, SPI, PIN, C, D> {
+    /// Returns the address of the next byte that will be read.
+    pub fn address(&self) -> u32 {
+        self.addr
+    }
+
+    /// Reads the next `buf.len()` bytes, resuming the transfer first if it is
+    /// paused.
+    pub fn read(&mut self, buf: &mut [u8]) -> Result<(), Error<SPI, PIN>> {
+        self.flash.check_range(self.addr, buf.len())?;
+        if self.held {
+            self.resume()?;
+        }
+
+        for chunk in buf.chunks_mut(self.flash.state.max_transfer_size) {
+            self.flash.spi.read(chunk).map_err(Error::Spi)?;
+        }
+
+        trace!("read {:#05x}: {:?}", self.addr, HexSlice(&*buf));
+        self.addr = ((u64::from(self.addr) + buf.len() as u64) % u64::from(C::CAPACITY)) as u32;
+        Ok(())
+    }
+
+    /// Pauses the transfer by driving `HOLD` low. The chip ignores the bus until
+    /// the transfer is resumed, so other devices can use it in the meantime.
+    ///
+    /// Fails with [`Error::MissingPin`] if the driver was initialized without a
+    /// `HOLD` pin.
+    pub fn hold(&mut self) -> Result<(), Error<SPI, PIN>> {
+        let hold = self.flash.hold.as_mut().ok_or(Error::MissingPin)?;
+        hold.set_low().map_err(Error::Gpio)?;
+        self.held = true;
+        Ok(())
+    }
+
+    /// Resumes a paused transfer by driving `HOLD` high.
+    pub fn resume(&mut self) -> Result<(), Error<SPI, PIN>> {
+        let hold = self.flash.hold.as_mut().ok_or(Error::MissingPin)?;
+        hold.set_high().map_err(Error::Gpio)?;
+        self.held = false;
         Ok(())
     }
-}
\ No newline at end of file
+
+    /// Ends the `READ` instruction, resuming the transfer first if it is paused,
+    /// and deselects the chip.
+    pub fn finish(mut self) -> Result<(), Error<SPI, PIN>> {
+        self.close()
+    }
+
+    fn close(&mut self) -> Result<(), Error<SPI, PIN>> {
+        if !self.open {
+            return Ok(());
+        }
+        self.open = false;
+
+        // Deselect the chip even if HOLD can't be released
+        let resumed = if self.held { self.resume() } else { Ok(()) };
+        self.flash.deselect()?;
+        resumed
+    }
+}
+
+impl<SPI: SpiDevice, PIN: OutputPin, C: Chip, D: DelayNs> Drop for HeldRead<'_, SPI, PIN, C, D> {
+    fn drop(&mut self) {
+        if self.close().is_err() {
+            error!("HeldRead: failed to deselect the chip");
+        }
+    }
+}
@@ -0,0 +1,132 @@
+//! A key/value map and a queue stored in the EEPROM, built on the
+//! [`sequential_storage`] crate.
+//!
+//! [`Flash::map`] and [`Flash::queue`] keep their items in an address range of
+//! the chip, appending each change to the range and erasing the oldest page
+//! once it is full. Writes are thus spread over all pages of the range, and an
+//! interrupted write leaves the previous value readable.
+//!
+//! `sequential-storage` is async, so [`Flash`] implements the
+//! `embedded-storage-async` NOR flash traits on top of its blocking ones. The
+//! returned futures never wait, and can be run with any `block_on`:
+//!
+//! ```
+//! # #[cfg(feature = "sim")] {
+//! use m95320::m95320::Flash;
+//! use m95320::sim::Simulator;
+//! # use embassy_futures::block_on;
+//!
+//! let sim = Simulator::new();
+//! let mut flash = Flash::init(sim.device()).unwrap();
+//! let mut settings = flash.map::<u8>(0x0C00..0x1000).unwrap();
+//!
+//! let mut buf = [0; 16];
+//! block_on(settings.store_item(&mut buf, &1, &5000u32)).unwrap();
+//! assert_eq!(block_on(settings.fetch_item::<u32>(&mut buf, &1)).unwrap(), Some(5000));
+//! # }
+//! ```
+//!
+//! Items have to fit into a page together with `sequential-storage`'s
+//! bookkeeping, which leaves 22 bytes for the key and value on the M95320.
+
+use core::ops::Range;
+
+use embedded_hal::delay::DelayNs;
+use embedded_hal::digital::OutputPin;
+use embedded_hal::spi::SpiDevice;
+use embedded_storage_async::nor_flash::{MultiwriteNorFlash, NorFlash, ReadNorFlash};
+use sequential_storage::cache::{Cache, Uncached};
+use sequential_storage::map::{Key, MapConfig, MapStorage};
+use sequential_storage::queue::{QueueConfig, QueueStorage};
+
+use crate::chip::Chip;
+use crate::m95320::Flash;
+use crate::Error;
+
+/// The `sequential-storage` crate, to name its types in the version this
+/// crate is built with.
+pub use sequential_storage;
+
+/// A key/value map in a range of the chip `F`, created with [`Flash::map`].
+pub type Map<'a, K, F> = MapStorage<K, &'a mut F, NoCache<K>>;
+
+/// A queue in a range of the chip `F`, created with [`Flash::queue`].
+pub type Queue<'a, F> = QueueStorage<&'a mut F, NoCache<()>>;
+
+/// The cache of a [`Map`] or [`Queue`]. Nothing is cached, since reading the
+/// EEPROM is cheap compared to the RAM a cache takes.
+pub type NoCache<K> = Cache<Uncached, Uncached, Uncached, K>;
+
+impl<SPI: SpiDevice, PIN: OutputPin, C: Chip, D: DelayNs> ReadNorFlash for Flash<SPI, PIN, C, D> {
+    const READ_SIZE: usize = 1;
+
+    async fn read(&mut self, offset: u32, bytes: &mut [u8]) -> Result<(), Self::Error> {
+        embedded_storage::nor_flash::ReadNorFlash::read(self, offset, bytes)
+    }
+
+    fn capacity(&self) -> usize {
+        C::CAPACITY as usize
+    }
+}
+
+impl<SPI: SpiDevice, PIN: OutputPin, C: Chip, D: DelayNs> NorFlash for Flash<SPI, PIN, C, D> {
+    const WRITE_SIZE: usize = 1;
+    const ERASE_SIZE: usize = C::PAGE_SIZE as usize;
+
+    async fn erase(&mut self, from: u32, to: u32) -> Result<(), Self::Error> {
+        embedded_storage::nor_flash::NorFlash::erase(self, from, to)
+    }
+
+    async fn write(&mut self, offset: u32, bytes: &[u8]) -> Result<(), Self::Error> {
+        embedded_storage::nor_flash::NorFlash::write(self, offset, bytes)
+    }
+}
+
+impl<SPI: SpiDevice, PIN: OutputPin, C: Chip, D: DelayNs> MultiwriteNorFlash for Flash<SPI, PIN, C, D> {}
+
+impl<SPI: SpiDevice, PIN: OutputPin, C: Chip, D: DelayNs> Flash<SPI, PIN, C, D> {
+    /// Returns a key/value map with keys `K`, stored in the pages of `range`.
+    ///
+    /// Items stored by an earlier map in the same range are found again. See
+    /// [`MapStorage`] for how to store and fetch items, and [`Flash::queue`] for
+    /// the requirements on `range`.
+    pub fn map<K: Key>(&mut self, range: Range<u32>) -> Result<Map<'_, K, Self>, Error<SPI, PIN>> {
+        self.check_storage_range(&range)?;
+        let config = MapConfig::try_new(range.clone())
+            .map_err(|_| Error::RangeTooSmall { start: range.start, end: range.end })?;
+        Ok(MapStorage::new(self, config, Cache::new_uncached()))
+    }
+
+    /// Returns a first-in, first-out queue of byte slices, stored in the pages
+    /// of `range`.
+    ///
+    /// Items pushed by an earlier queue in the same range are found again. See
+    /// [`QueueStorage`] for how to push and pop items.
+    ///
+    /// Fails with [`Error::NotPageAligned`] if `range` does not start and end
+    /// on page boundaries, with [`Error::AddressOutOfBounds`] if it is empty or
+    /// goes past the end of the chip, and with [`Error::RangeTooSmall`] if it
+    /// is smaller than two pages.
+    pub fn queue(&mut self, range: Range<u32>) -> Result<Queue<'_, Self>, Error<SPI, PIN>> {
+        self.check_storage_range(&range)?;
+        let config = QueueConfig::try_new(range.clone())
+            .map_err(|_| Error::RangeTooSmall { start: range.start, end: range.end })?;
+        Ok(QueueStorage::new(self, config, Cache::new_uncached()))
+    }
+
+    fn check_storage_range(&self, range: &Range<u32>) -> Result<(), Error<SPI, PIN>> {
+        for addr in [range.start, range.end].iter() {
+            if addr % u32::from(C::PAGE_SIZE) != 0 {
+                return Err(Error::NotPageAligned(*addr));
+            }
+        }
+        if range.start >= range.end || range.end > C::CAPACITY {
+            let len = range.end.saturating_sub(range.start) as usize;
+            return Err(Error::AddressOutOfBounds { start: range.start, len });
+        }
+        if range.end - range.start < 2 * u32::from(C::PAGE_SIZE) {
+            return Err(Error::RangeTooSmall { start: range.start, end: range.end });
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,524 @@
+//! In-memory simulation of an M95320, for running the driver without hardware.
+//!
+//! [`Simulator`] keeps the 4 KiB memory array and the status register of a
+//! single chip and decodes the instruction set the driver uses. It hands out
+//! a [`SimDevice`], which can be passed to
+//! [`Flash::init`](crate::m95320::Flash::init) in place of a real SPI device:
+//!
+//! ```
+//! use m95320::prelude::*;
+//! use m95320::m95320::Flash;
+//! use m95320::sim::Simulator;
+//!
+//! let sim = Simulator::new();
+//! let mut flash = Flash::init(sim.device()).unwrap();
+//!
+//! flash.write_bytes(0x10, &[1, 2, 3]).unwrap();
+//!
+//! let mut buf = [0; 3];
+//! sim.read_memory(0x10, &mut buf);
+//! assert_eq!(buf, [1, 2, 3]);
+//! ```
+//!
+//! With the `async` feature, [`SimDevice`] also implements the `embedded-hal-async`
+//! `SpiDevice` trait. With the `embedded-hal-02` feature, [`Simulator::split`]
+//! instead hands out an `embedded-hal` 0.2 bus and a paired chip-select pin.
+//!
+//! The model follows the datasheet where the driver can observe it:
+//!
+//! * `WRITE` and `WRSR` are only executed if the Write Enable Latch is set,
+//!   and always reset it once `S` is driven high.
+//! * Data bytes of a `WRITE` past the end of the page wrap around to the start
+//!   of the same page. `READ` rolls over from the last address to `0x000`.
+//! * Writes into an area protected by the `BP1`/`BP0` bits are ignored.
+//! * `WRSR` is ignored in Hardware Protected Mode, when the `SRWD` bit is set
+//!   and the `W` input from [`Simulator::write_protect_pin`] is low.
+//! * The 32 byte Identification Page of the M95320-D can be read, written and
+//!   locked. Once locked, writes to it are ignored.
+//! * While the `HOLD` input from [`Simulator::hold_pin`] is low, the chip ignores
+//!   the bus without ending the current instruction.
+//! * After a write cycle is started, `WIP` reads as set for a configurable
+//!   number of status register reads, and every instruction other than `RDSR`
+//!   is ignored until it clears.
+
+use core::cell::RefCell;
+use core::convert::Infallible;
+
+use embedded_hal::digital;
+use embedded_hal::spi::{ErrorType, Operation, SpiDevice};
+#[cfg(feature = "embedded-hal-02")]
+use embedded_hal_02::{blocking::spi::Transfer, digital::v2::OutputPin};
+
+use crate::chip::{Chip, M95320};
+use crate::m95320::{Opcode, Status, ID_LOCK_ADDRESS, ID_LOCK_BIT};
+use crate::utils::HexSlice;
+
+/// Size of the simulated memory array in bytes.
+pub const MEMORY_SIZE: usize = M95320::CAPACITY as usize;
+
+const PAGE_SIZE: u16 = M95320::PAGE_SIZE;
+const PAGE: usize = PAGE_SIZE as usize;
+
+/// Number of status register reads that report `WIP` after a write cycle
+/// starts, unless changed with [`Simulator::set_write_cycle_polls`].
+const DEFAULT_WRITE_CYCLE_POLLS: u8 = 3;
+
+/// A simulated M95320 chip.
+///
+/// See the [module documentation](self) for what is modeled.
+#[derive(Debug)]
+pub struct Simulator {
+    state: RefCell<State>,
+}
+
+/// The SPI interface of a [`Simulator`], obtained from [`Simulator::device`]
+/// or [`Simulator::device_without_cs`].
+#[derive(Debug)]
+pub struct SimDevice<'a> {
+    state: &'a RefCell<State>,
+    /// Whether each transaction selects the chip for its duration.
+    drives_cs: bool,
+}
+
+/// A control input of a [`Simulator`], obtained from
+/// [`Simulator::chip_select_pin`], [`Simulator::write_protect_pin`] or
+/// [`Simulator::hold_pin`].
+#[derive(Debug)]
+pub struct SimPin<'a> {
+    state: &'a RefCell<State>,
+    input: Input,
+}
+
+#[derive(Debug, Clone, Copy)]
+enum Input {
+    ChipSelect,
+    WriteProtect,
+    Hold,
+}
+
+/// The SPI bus of a [`Simulator`], obtained from [`Simulator::split`].
+#[cfg(feature = "embedded-hal-02")]
+#[derive(Debug)]
+pub struct SimSpi<'a> {
+    state: &'a RefCell<State>,
+}
+
+/// The `S` (chip select) input of a [`Simulator`], obtained from
+/// [`Simulator::split`].
+#[cfg(feature = "embedded-hal-02")]
+#[derive(Debug)]
+pub struct SimCs<'a> {
+    state: &'a RefCell<State>,
+}
+
+#[derive(Debug)]
+struct State {
+    memory: [u8; MEMORY_SIZE],
+    /// Status register without the `WIP` bit, which is derived from `busy`.
+    status: Status,
+    /// Remaining status register reads that report a write in progress.
+    busy: u8,
+    write_cycle_polls: u8,
+    selected: bool,
+    /// Whether `W` is driven low.
+    write_protect: bool,
+    /// Whether `HOLD` is driven low.
+    held: bool,
+    phase: Phase,
+    /// Page latch filled by the data bytes of a `WRITE` or Write
+    /// Identification Page instruction.
+    latch: [u8; PAGE],
+    id_page: [u8; PAGE],
+    id_locked: bool,
+}
+
+#[derive(Debug, Clone, Copy)]
+enum Phase {
+    /// Waiting for the instruction byte.
+    Instruction,
+    /// The rest of this selection is ignored.
+    Ignored,
+    /// Shifting out the status register.
+    ReadStatus,
+    /// Waiting for, or holding, the new status register value.
+    WriteStatus(Option<u8>),
+    /// Shifting in the two address bytes of an instruction.
+    Address { opcode: Opcode, addr: u16, remaining: u8 },
+    /// Shifting out data, starting at `addr`.
+    Read { addr: u16 },
+    /// Shifting data into the page latch at `offset`.
+    Write { page: u16, offset: u16, dirty: bool },
+    /// Shifting out the Identification Page, starting at `offset`.
+    ReadId { offset: u16 },
+    /// Shifting Identification Page data into the page latch at `offset`.
+    WriteId { offset: u16, dirty: bool },
+    /// Shifting out the lock status of the Identification Page.
+    ReadLockStatus,
+    /// Waiting for, or holding, the Lock ID data byte.
+    LockId(Option<u8>),
+}
+
+impl Simulator {
+    /// Creates a simulated chip in its delivery state: every byte is `0xFF`
+    /// and the status register is clear.
+    pub fn new() -> Self {
+        Self {
+            state: RefCell::new(State {
+                memory: [0xFF; MEMORY_SIZE],
+                status: Status::empty(),
+                busy: 0,
+                write_cycle_polls: DEFAULT_WRITE_CYCLE_POLLS,
+                selected: false,
+                write_protect: false,
+                held: false,
+                phase: Phase::Instruction,
+                latch: [0; PAGE],
+                id_page: [0xFF; PAGE],
+                id_locked: false,
+            }),
+        }
+    }
+
+    /// Returns the SPI interface of this chip, which selects the chip for the
+    /// duration of each transaction.
+    pub fn device(&self) -> SimDevice<'_> {
+        SimDevice { state: &self.state, drives_cs: true }
+    }
+
+    /// Returns the SPI interface of this chip without chip select, like a
+    /// device on a shared bus whose chip select is driven separately through
+    /// [`Simulator::chip_select_pin`].
+    pub fn device_without_cs(&self) -> SimDevice<'_> {
+        SimDevice { state: &self.state, drives_cs: false }
+    }
+
+    /// Returns the `S` input of this chip.
+    pub fn chip_select_pin(&self) -> SimPin<'_> {
+        SimPin { state: &self.state, input: Input::ChipSelect }
+    }
+
+    /// Returns the `W` input of this chip. It is high until driven low.
+    pub fn write_protect_pin(&self) -> SimPin<'_> {
+        SimPin { state: &self.state, input: Input::WriteProtect }
+    }
+
+    /// Returns the `HOLD` input of this chip. It is high until driven low.
+    pub fn hold_pin(&self) -> SimPin<'_> {
+        SimPin { state: &self.state, input: Input::Hold }
+    }
+
+    /// Returns the `embedded-hal` 0.2 SPI bus and chip select line of this chip.
+    #[cfg(feature = "embedded-hal-02")]
+    pub fn split(&self) -> (SimSpi<'_>, SimCs<'_>) {
+        (SimSpi { state: &self.state }, SimCs { state: &self.state })
+    }
+
+    /// Copies `buf.len()` bytes of the memory array starting at `addr` into
+    /// `buf`, without going through the SPI interface.
+    pub fn read_memory(&self, addr: u16, buf: &mut [u8]) {
+        let state = self.state.borrow();
+        let start = usize::from(addr);
+        buf.copy_from_slice(&state.memory[start..start + buf.len()]);
+    }
+
+    /// Overwrites the memory array starting at `addr` with `data`, without
+    /// going through the SPI interface.
+    pub fn load_memory(&self, addr: u16, data: &[u8]) {
+        let mut state = self.state.borrow_mut();
+        let start = usize::from(addr);
+        state.memory[start..start + data.len()].copy_from_slice(data);
+    }
+
+    /// Returns the current value of the status register, including `WIP`.
+    pub fn status(&self) -> Status {
+        self.state.borrow().status_register()
+    }
+
+    /// Sets the number of status register reads that report `WIP` after a
+    /// write cycle starts. `0` makes write cycles complete instantly.
+    pub fn set_write_cycle_polls(&self, polls: u8) {
+        self.state.borrow_mut().write_cycle_polls = polls;
+    }
+}
+
+impl Default for Simulator {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl State {
+    fn status_register(&self) -> Status {
+        if self.busy > 0 {
+            self.status | Status::WRITE_IN_PROGRESS
+        } else {
+            self.status
+        }
+    }
+
+    fn select(&mut self) {
+        if !self.selected {
+            self.selected = true;
+            self.phase = Phase::Instruction;
+        }
+    }
+
+    fn deselect(&mut self) {
+        if !self.selected {
+            return;
+        }
+        self.selected = false;
+
+        match self.phase {
+            Phase::Write { page, dirty: true, .. } => {
+                if self.status.contains(Status::WRITE_ENABLE_LATCH) && !self.is_protected(page) {
+                    let start = usize::from(page);
+                    self.memory[start..start + PAGE].copy_from_slice(&self.latch);
+                    self.busy = self.write_cycle_polls;
+                }
+                self.status.remove(Status::WRITE_ENABLE_LATCH);
+            }
+            Phase::WriteId { dirty: true, .. } => {
+                if self.status.contains(Status::WRITE_ENABLE_LATCH) && !self.id_locked {
+                    self.id_page = self.latch;
+                    self.busy = self.write_cycle_polls;
+                }
+                self.status.remove(Status::WRITE_ENABLE_LATCH);
+            }
+            Phase::LockId(Some(value)) => {
+                if self.status.contains(Status::WRITE_ENABLE_LATCH) && value & ID_LOCK_BIT != 0 {
+                    self.id_locked = true;
+                    self.busy = self.write_cycle_polls;
+                }
+                self.status.remove(Status::WRITE_ENABLE_LATCH);
+            }
+            Phase::WriteStatus(Some(value)) => {
+                let hardware_protected =
+                    self.write_protect && self.status.contains(Status::STATUS_REGISTER_WRITE_DISABLE);
+                if self.status.contains(Status::WRITE_ENABLE_LATCH) && !hardware_protected {
+                    let writable = Status::BLOCK_PROTECT | Status::STATUS_REGISTER_WRITE_DISABLE;
+                    self.status = (self.status - writable)
+                        | (Status::from_bits_truncate(value) & writable);
+                    self.busy = self.write_cycle_polls;
+                }
+                self.status.remove(Status::WRITE_ENABLE_LATCH);
+            }
+            _ => {}
+        }
+
+        self.phase = Phase::Instruction;
+    }
+
+    /// Returns whether the page starting at `page` lies in the area protected
+    /// by the `BP1`/`BP0` bits.
+    fn is_protected(&self, page: u16) -> bool {
+        self.status.protected_area().overlaps::<M95320>(page.into(), PAGE)
+    }
+
+    /// Clocks one byte in on `D` and returns the byte clocked out on `Q`.
+    fn exchange(&mut self, mosi: u8) -> u8 {
+        if !self.selected || self.held {
+            // Q is high impedance while the chip is deselected or on hold.
+            return 0xFF;
+        }
+
+        match self.phase {
+            Phase::Instruction => {
+                self.phase = match Opcode::from_byte(mosi) {
+                    Some(Opcode::ReadStatusRegister) => Phase::ReadStatus,
+                    // Only RDSR is accepted while a write cycle is in progress.
+                    _ if self.busy > 0 => Phase::Ignored,
+                    Some(Opcode::WriteEnable) => {
+                        self.status.insert(Status::WRITE_ENABLE_LATCH);
+                        Phase::Ignored
+                    }
+                    Some(Opcode::WriteDisable) => {
+                        self.status.remove(Status::WRITE_ENABLE_LATCH);
+                        Phase::Ignored
+                    }
+                    Some(Opcode::WriteStatusRegister) => Phase::WriteStatus(None),
+                    Some(opcode) => Phase::Address { opcode, addr: 0, remaining: 2 },
+                    None => Phase::Ignored,
+                };
+                0xFF
+            }
+            Phase::Ignored => 0xFF,
+            Phase::ReadStatus => {
+                let status = self.status_register();
+                self.busy = self.busy.saturating_sub(1);
+                status.bits()
+            }
+            Phase::WriteStatus(value) => {
+                // Only the first byte after the instruction is used.
+                self.phase = Phase::WriteStatus(value.or(Some(mosi)));
+                0xFF
+            }
+            Phase::Address { opcode, addr, remaining } => {
+                let addr = (addr << 8) | u16::from(mosi);
+                if remaining > 1 {
+                    self.phase = Phase::Address { opcode, addr, remaining: remaining - 1 };
+                    return 0xFF;
+                }
+
+                // The four most significant address bits are don't care.
+                let memory_addr = addr & (MEMORY_SIZE as u16 - 1);
+                let locking = u32::from(addr) & ID_LOCK_ADDRESS != 0;
+                self.phase = match opcode {
+                    Opcode::Read => Phase::Read { addr: memory_addr },
+                    Opcode::Write => {
+                        let page = memory_addr - memory_addr % PAGE_SIZE;
+                        let start = usize::from(page);
+                        self.latch.copy_from_slice(&self.memory[start..start + PAGE]);
+                        Phase::Write { page, offset: memory_addr % PAGE_SIZE, dirty: false }
+                    }
+                    Opcode::ReadIdentificationPage if locking => Phase::ReadLockStatus,
+                    Opcode::ReadIdentificationPage => Phase::ReadId { offset: addr % PAGE_SIZE },
+                    Opcode::WriteIdentificationPage if locking => Phase::LockId(None),
+                    Opcode::WriteIdentificationPage => {
+                        self.latch = self.id_page;
+                        Phase::WriteId { offset: addr % PAGE_SIZE, dirty: false }
+                    }
+                    _ => Phase::Ignored,
+                };
+                0xFF
+            }
+            Phase::Read { addr } => {
+                self.phase = Phase::Read { addr: (addr + 1) % MEMORY_SIZE as u16 };
+                self.memory[usize::from(addr)]
+            }
+            Phase::Write { page, offset, .. } => {
+                self.latch[usize::from(offset)] = mosi;
+                self.phase = Phase::Write { page, offset: (offset + 1) % PAGE_SIZE, dirty: true };
+                0xFF
+            }
+            Phase::ReadId { offset } => {
+                self.phase = Phase::ReadId { offset: (offset + 1) % PAGE_SIZE };
+                self.id_page[usize::from(offset)]
+            }
+            Phase::WriteId { offset, .. } => {
+                self.latch[usize::from(offset)] = mosi;
+                self.phase = Phase::WriteId { offset: (offset + 1) % PAGE_SIZE, dirty: true };
+                0xFF
+            }
+            Phase::ReadLockStatus => {
+                if self.id_locked { ID_LOCK_BIT } else { 0 }
+            }
+            Phase::LockId(value) => {
+                self.phase = Phase::LockId(value.or(Some(mosi)));
+                0xFF
+            }
+        }
+    }
+
+    /// Clocks all of `words` in on `D`, replacing them with the bytes clocked
+    /// out on `Q`.
+    fn exchange_all(&mut self, words: &mut [u8]) {
+        trace!("sim: D = {:?}", HexSlice(&*words));
+        for word in words.iter_mut() {
+            *word = self.exchange(*word);
+        }
+        trace!("sim: Q = {:?}", HexSlice(&*words));
+    }
+
+    /// Runs `operations`, selecting the chip for their duration if `drives_cs`.
+    fn transaction(&mut self, operations: &mut [Operation<'_, u8>], drives_cs: bool) {
+        if drives_cs {
+            self.select();
+        }
+        for operation in operations {
+            match operation {
+                Operation::Read(buf) => {
+                    buf.iter_mut().for_each(|byte| *byte = 0);
+                    self.exchange_all(buf);
+                }
+                Operation::Write(data) => {
+                    for byte in data.iter() {
+                        self.exchange(*byte);
+                    }
+                }
+                Operation::Transfer(read, write) => {
+                    for i in 0..read.len().max(write.len()) {
+                        let miso = self.exchange(write.get(i).copied().unwrap_or(0));
+                        if let Some(byte) = read.get_mut(i) {
+                            *byte = miso;
+                        }
+                    }
+                }
+                Operation::TransferInPlace(buf) => self.exchange_all(buf),
+                Operation::DelayNs(_) => {}
+            }
+        }
+        if drives_cs {
+            self.deselect();
+        }
+    }
+
+    fn set_input(&mut self, input: Input, high: bool) {
+        match input {
+            Input::ChipSelect if high => self.deselect(),
+            Input::ChipSelect => self.select(),
+            Input::WriteProtect => self.write_protect = !high,
+            Input::Hold => self.held = !high,
+        }
+    }
+}
+
+impl ErrorType for SimDevice<'_> {
+    type Error = Infallible;
+}
+
+impl SpiDevice for SimDevice<'_> {
+    fn transaction(&mut self, operations: &mut [Operation<'_, u8>]) -> Result<(), Self::Error> {
+        self.state.borrow_mut().transaction(operations, self.drives_cs);
+        Ok(())
+    }
+}
+
+#[cfg(feature = "async")]
+impl embedded_hal_async::spi::SpiDevice for SimDevice<'_> {
+    async fn transaction(&mut self, operations: &mut [Operation<'_, u8>]) -> Result<(), Self::Error> {
+        self.state.borrow_mut().transaction(operations, self.drives_cs);
+        Ok(())
+    }
+}
+
+impl digital::ErrorType for SimPin<'_> {
+    type Error = Infallible;
+}
+
+impl digital::OutputPin for SimPin<'_> {
+    fn set_low(&mut self) -> Result<(), Self::Error> {
+        self.state.borrow_mut().set_input(self.input, false);
+        Ok(())
+    }
+
+    fn set_high(&mut self) -> Result<(), Self::Error> {
+        self.state.borrow_mut().set_input(self.input, true);
+        Ok(())
+    }
+}
+
+#[cfg(feature = "embedded-hal-02")]
+impl Transfer<u8> for SimSpi<'_> {
+    type Error = Infallible;
+
+    fn transfer<'w>(&mut self, words: &'w mut [u8]) -> Result<&'w [u8], Self::Error> {
+        self.state.borrow_mut().exchange_all(words);
+        Ok(words)
+    }
+}
+
+#[cfg(feature = "embedded-hal-02")]
+impl OutputPin for SimCs<'_> {
+    type Error = Infallible;
+
+    fn set_low(&mut self) -> Result<(), Self::Error> {
+        self.state.borrow_mut().select();
+        Ok(())
+    }
+
+    fn set_high(&mut self) -> Result<(), Self::Error> {
+        self.state.borrow_mut().deselect();
+        Ok(())
+    }
+}
@@ -0,0 +1,67 @@
+//! Implementations of the `spi-memory` traits.
+//!
+//! `spi-memory` drivers talk to an `embedded-hal` 0.2 bus and chip-select pin,
+//! so the traits are implemented for a driver on top of a [`LegacyDevice`],
+//! with the bus and pin types of the device.
+
+use core::fmt::Debug;
+
+use embedded_hal::delay::DelayNs;
+use embedded_hal_02::blocking::spi::Transfer;
+use embedded_hal_02::digital::v2::OutputPin;
+
+use crate::chip::Chip;
+use crate::compat::{LegacyDevice, LegacyError};
+use crate::m95320::Flash;
+use crate::{BlockDevice, Error, NoPin, Read};
+
+/// Converts an error of the driver into the error type of `spi-memory`.
+///
+/// `spi-memory` only knows about bus, pin and status register errors. The
+/// others, like an access out of bounds, are reported as
+/// [`spi_memory::Error::UnexpectedStatus`] after being logged.
+fn into_spi_memory_error<SPI: Transfer<u8>, CS: OutputPin>(error: Error<LegacyDevice<SPI, CS>>) -> spi_memory::Error<SPI, CS>
+where
+    SPI::Error: Debug,
+    CS::Error: Debug,
+{
+    match error {
+        Error::Spi(LegacyError::Spi(spi)) => spi_memory::Error::Spi(spi),
+        Error::Spi(LegacyError::Gpio(gpio)) => spi_memory::Error::Gpio(gpio),
+        Error::UnexpectedStatus => spi_memory::Error::UnexpectedStatus,
+        other => {
+            error!("spi-memory: {:?}", other);
+            spi_memory::Error::UnexpectedStatus
+        }
+    }
+}
+
+impl<SPI: Transfer<u8>, CS: OutputPin, C: Chip, D: DelayNs> spi_memory::Read<u32, SPI, CS> for Flash<LegacyDevice<SPI, CS>, NoPin, C, D>
+where
+    SPI::Error: Debug,
+    CS::Error: Debug,
+{
+    fn read(&mut self, addr: u32, buf: &mut [u8]) -> Result<(), spi_memory::Error<SPI, CS>> {
+        Read::read(self, addr, buf).map_err(into_spi_memory_error)
+    }
+}
+
+impl<SPI: Transfer<u8>, CS: OutputPin, C: Chip, D: DelayNs> spi_memory::BlockDevice<u32, SPI, CS> for Flash<LegacyDevice<SPI, CS>, NoPin, C, D>
+where
+    SPI::Error: Debug,
+    CS::Error: Debug,
+{
+    /// Erases `amount` pages starting at `addr`, which has to be on a page
+    /// boundary. Pages are much smaller than the sectors of SPI flash chips.
+    fn erase_sectors(&mut self, addr: u32, amount: usize) -> Result<(), spi_memory::Error<SPI, CS>> {
+        BlockDevice::erase_sectors(self, addr, amount).map_err(into_spi_memory_error)
+    }
+
+    fn erase_all(&mut self) -> Result<(), spi_memory::Error<SPI, CS>> {
+        BlockDevice::erase_all(self).map_err(into_spi_memory_error)
+    }
+
+    fn write_bytes(&mut self, addr: u32, data: &mut [u8]) -> Result<(), spi_memory::Error<SPI, CS>> {
+        BlockDevice::write_bytes(self, addr, data).map_err(into_spi_memory_error)
+    }
+}
@@ -0,0 +1,110 @@
+//! Implementations of the `embedded-storage` traits.
+//!
+//! The EEPROM can write single bytes without erasing them first, so it
+//! implements [`Storage`] directly, and [`MultiwriteNorFlash`] with a write size
+//! of 1 and pages as erase blocks. Accesses through these traits never wrap
+//! past the end of the memory array, whatever the wrap policy of the driver.
+
+use embedded_hal::delay::DelayNs;
+use embedded_hal::digital::OutputPin;
+use embedded_hal::spi::SpiDevice;
+use embedded_storage::nor_flash::{
+    ErrorType, MultiwriteNorFlash, NorFlash, NorFlashError, NorFlashErrorKind, ReadNorFlash,
+};
+use embedded_storage::{ReadStorage, Storage};
+
+use crate::chip::Chip;
+use crate::m95320::{in_bounds, Flash, WrapPolicy};
+use crate::{BlockDevice, Error, Read};
+
+/// Value of erased bytes expected by the NOR flash traits.
+const NOR_ERASED: u8 = 0xFF;
+
+impl<SPI: SpiDevice, PIN: OutputPin> NorFlashError for Error<SPI, PIN> {
+    fn kind(&self) -> NorFlashErrorKind {
+        match self {
+            Error::AddressOutOfBounds { .. } => NorFlashErrorKind::OutOfBounds,
+            Error::NotPageAligned(_) => NorFlashErrorKind::NotAligned,
+            _ => NorFlashErrorKind::Other,
+        }
+    }
+}
+
+/// Fails with [`Error::AddressOutOfBounds`] if `len` bytes starting at `addr`
+/// run past the end of chip `C`.
+fn check_bounds<C: Chip, SPI: SpiDevice, PIN: OutputPin>(addr: u32, len: usize) -> Result<(), Error<SPI, PIN>> {
+    if in_bounds::<C>(addr, len, WrapPolicy::Reject) {
+        Ok(())
+    } else {
+        Err(Error::AddressOutOfBounds { start: addr, len })
+    }
+}
+
+impl<SPI: SpiDevice, PIN: OutputPin, C: Chip, D: DelayNs> ReadStorage for Flash<SPI, PIN, C, D> {
+    type Error = Error<SPI, PIN>;
+
+    fn read(&mut self, offset: u32, bytes: &mut [u8]) -> Result<(), Self::Error> {
+        check_bounds::<C, SPI, PIN>(offset, bytes.len())?;
+        Read::read(self, offset, bytes)
+    }
+
+    fn capacity(&self) -> usize {
+        C::CAPACITY as usize
+    }
+}
+
+impl<SPI: SpiDevice, PIN: OutputPin, C: Chip, D: DelayNs> Storage for Flash<SPI, PIN, C, D> {
+    fn write(&mut self, offset: u32, bytes: &[u8]) -> Result<(), Self::Error> {
+        check_bounds::<C, SPI, PIN>(offset, bytes.len())?;
+        BlockDevice::write_bytes(self, offset, bytes)
+    }
+}
+
+impl<SPI: SpiDevice, PIN: OutputPin, C: Chip, D: DelayNs> ErrorType for Flash<SPI, PIN, C, D> {
+    type Error = Error<SPI, PIN>;
+}
+
+impl<SPI: SpiDevice, PIN: OutputPin, C: Chip, D: DelayNs> ReadNorFlash for Flash<SPI, PIN, C, D> {
+    const READ_SIZE: usize = 1;
+
+    fn read(&mut self, offset: u32, bytes: &mut [u8]) -> Result<(), Self::Error> {
+        ReadStorage::read(self, offset, bytes)
+    }
+
+    fn capacity(&self) -> usize {
+        C::CAPACITY as usize
+    }
+}
+
+impl<SPI: SpiDevice, PIN: OutputPin, C: Chip, D: DelayNs> NorFlash for Flash<SPI, PIN, C, D> {
+    const WRITE_SIZE: usize = 1;
+    const ERASE_SIZE: usize = C::PAGE_SIZE as usize;
+
+    /// Erases the pages in `from..to` to `0xFF`, whatever the
+    /// [erase fill](Flash::set_erase_fill) of the driver.
+    ///
+    /// Fails with [`Error::AddressOutOfBounds`] if `to` is before `from` or
+    /// past the end of the chip, and with [`Error::NotPageAligned`] if either
+    /// is not on a page boundary.
+    fn erase(&mut self, from: u32, to: u32) -> Result<(), Self::Error> {
+        if to < from {
+            return Err(Error::AddressOutOfBounds { start: from, len: 0 });
+        }
+        let len = (to - from) as usize;
+        check_bounds::<C, SPI, PIN>(from, len)?;
+        for addr in [from, to].iter() {
+            if addr % u32::from(C::PAGE_SIZE) != 0 {
+                return Err(Error::NotPageAligned(*addr));
+            }
+        }
+
+        self.fill(from, len, NOR_ERASED)
+    }
+
+    fn write(&mut self, offset: u32, bytes: &[u8]) -> Result<(), Self::Error> {
+        Storage::write(self, offset, bytes)
+    }
+}
+
+/// Every byte can be written any number of times between erases.
+impl<SPI: SpiDevice, PIN: OutputPin, C: Chip, D: DelayNs> MultiwriteNorFlash for Flash<SPI, PIN, C, D> {}
@@ -0,0 +1,214 @@
+//! These tests run several simulated chips on one bus through a `FlashArray`
+
+use std::convert::Infallible;
+
+use embedded_hal::spi::{ErrorType, Operation, SpiDevice};
+use m95320::prelude::*;
+use m95320::array::FlashArray;
+use m95320::m95320::{ProtectedArea, Status, WrapPolicy};
+use m95320::sim::{SimDevice, Simulator};
+
+/// A bus shared by several simulated chips, whose chip select is driven by the
+/// array. Deselected chips leave `Q` high, so the bytes they return are ANDed.
+struct Bus<'a>(Vec<SimDevice<'a>>);
+
+impl ErrorType for Bus<'_> {
+    type Error = Infallible;
+}
+
+impl SpiDevice for Bus<'_> {
+    fn transaction(&mut self, operations: &mut [Operation<'_, u8>]) -> Result<(), Infallible> {
+        for operation in operations {
+            match operation {
+                Operation::Write(data) => {
+                    for device in self.0.iter_mut() {
+                        device.write(data)?;
+                    }
+                }
+                Operation::Read(buf) => {
+                    buf.fill(0xFF);
+                    let mut miso = vec![0; buf.len()];
+                    for device in self.0.iter_mut() {
+                        device.read(&mut miso)?;
+                        buf.iter_mut().zip(miso.iter()).for_each(|(byte, q)| *byte &= q);
+                    }
+                }
+                Operation::Transfer(read, write) => {
+                    read.fill(0xFF);
+                    let mut miso = vec![0; read.len()];
+                    for device in self.0.iter_mut() {
+                        device.transfer(&mut miso, write)?;
+                        read.iter_mut().zip(miso.iter()).for_each(|(byte, q)| *byte &= q);
+                    }
+                }
+                Operation::TransferInPlace(words) => {
+                    let mosi = words.to_vec();
+                    words.fill(0xFF);
+                    for device in self.0.iter_mut() {
+                        let mut miso = mosi.clone();
+                        device.transfer_in_place(&mut miso)?;
+                        words.iter_mut().zip(miso.iter()).for_each(|(byte, q)| *byte &= q);
+                    }
+                }
+                Operation::DelayNs(_) => {}
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_array() {
+        let sims = [Simulator::new(), Simulator::new()];
+        // Slot 1 selects a chip that is not on the bus
+        let unconnected = Simulator::new();
+        let bus = Bus(vec![sims[0].device_without_cs(), sims[1].device_without_cs()]);
+        let chip_selects = [sims[0].chip_select_pin(), unconnected.chip_select_pin(), sims[1].chip_select_pin()];
+
+        let mut array = FlashArray::new(bus, chip_selects).expect("probe");
+        assert_eq!(array.present_slots().collect::<Vec<_>>(), vec![0, 2]);
+        assert_eq!(array.empty_slots().collect::<Vec<_>>(), vec![1]);
+
+        array.slot(0).expect("slot 0").write_bytes(0x0010, &[1, 2, 3]).expect("write slot 0");
+        {
+            let mut slot = array.slot(2).expect("slot 2");
+            slot.write_bytes(0x0010, &[4, 5, 6]).expect("write slot 2");
+        }
+        assert!(sims.iter().all(|sim| sim.status().contains(Status::WRITE_IN_PROGRESS)),
+            "slots are dropped during their write cycles");
+        array.flush().expect("flush");
+        assert!(sims.iter().all(|sim| !sim.status().contains(Status::WRITE_IN_PROGRESS)),
+            "flush waits for the write cycle of every slot");
+
+        let mut buf = [0; 3];
+        sims[0].read_memory(0x0010, &mut buf);
+        assert_eq!(buf, [1, 2, 3]);
+        sims[1].read_memory(0x0010, &mut buf);
+        assert_eq!(buf, [4, 5, 6]);
+
+        array.slot(2).expect("slot 2").read(0x0010, &mut buf).expect("read slot 2");
+        assert_eq!(buf, [4, 5, 6]);
+
+        match array.slot(1) {
+            Err(m95320::Error::EmptySlot(1)) => {}
+            other => panic!("expected an empty slot, got {:?}", other.map(|_| ())),
+        };
+        assert!(matches!(array.slot(3), Err(m95320::Error::EmptySlot(3))));
+    }
+
+    #[test]
+    fn test_slot_settings() {
+        let sims = [Simulator::new(), Simulator::new()];
+        let bus = Bus(vec![sims[0].device_without_cs(), sims[1].device_without_cs()]);
+        let mut array = FlashArray::new(bus, [sims[0].chip_select_pin(), sims[1].chip_select_pin()]).expect("probe");
+
+        {
+            let mut slot = array.slot(0).expect("slot 0");
+            slot.set_erase_fill(0x00);
+            slot.set_wrap_policy(WrapPolicy::Wrap);
+        }
+        array.slot(1).expect("slot 1").write_bytes(0x0000, &[1, 2, 3]).expect("write slot 1");
+
+        // Slot 0 still wraps and erases to 0x00, slot 1 kept the defaults
+        let mut slot = array.slot(0).expect("slot 0");
+        slot.write_bytes(0x0FFF, &[7, 8]).expect("write across the end");
+        slot.erase_sectors(0x0020, 1).expect("erase slot 0");
+        drop(slot);
+        assert!(array.slot(1).expect("slot 1").write_bytes(0x0FFF, &[7, 8]).is_err(), "slot 1 rejects the wrap");
+        array.flush().expect("flush");
+
+        let mut buf = [0xAA; 2];
+        sims[0].read_memory(0x0FFF, &mut buf[..1]);
+        sims[0].read_memory(0x0000, &mut buf[1..]);
+        assert_eq!(buf, [7, 8]);
+        let mut page = [0xAA; 32];
+        sims[0].read_memory(0x0020, &mut page);
+        assert!(page.iter().all(|&byte| byte == 0x00));
+    }
+
+    #[test]
+    fn test_slot_timeout() {
+        struct CountingDelay(u32);
+
+        impl embedded_hal::delay::DelayNs for CountingDelay {
+            fn delay_ns(&mut self, _ns: u32) {
+                self.0 += 1;
+            }
+        }
+
+        let sims = [Simulator::new(), Simulator::new()];
+        let bus = Bus(vec![sims[0].device_without_cs(), sims[1].device_without_cs()]);
+        let mut array = FlashArray::new(bus, [sims[0].chip_select_pin(), sims[1].chip_select_pin()])
+            .expect("probe")
+            .with_delay(CountingDelay(0));
+
+        // A chip that never finishes its write cycle
+        sims[0].set_write_cycle_polls(255);
+        array.slot(0).expect("slot 0").write_bytes(0x0010, &[1, 2, 3]).expect("write returns before the write cycle");
+
+        array.slot(1).expect("slot 1").write_bytes(0x0010, &[4, 5, 6]).expect("other slots are usable");
+        match array.slot(0).expect("slot 0").read(0x0010, &mut [0; 3]) {
+            Err(m95320::Error::Timeout(_)) => {}
+            other => panic!("expected a timeout, got {:?}", other),
+        };
+        assert!(matches!(array.flush(), Err(m95320::Error::Timeout(_))));
+    }
+
+    #[test]
+    fn test_write_interleaved() {
+        let sims = [Simulator::new(), Simulator::new()];
+        let unconnected = Simulator::new();
+        let bus = Bus(vec![sims[0].device_without_cs(), sims[1].device_without_cs()]);
+        let chip_selects = [sims[0].chip_select_pin(), unconnected.chip_select_pin(), sims[1].chip_select_pin()];
+        let mut array = FlashArray::new(bus, chip_selects).expect("probe");
+
+        let data: Vec<u8> = (0..40).collect();
+        array.write_interleaved(&[0, 2], 0x0010, &data).expect("write");
+        for sim in sims.iter() {
+            assert!(!sim.status().contains(Status::WRITE_IN_PROGRESS), "all write cycles finished");
+            let mut buf = [0; 40];
+            sim.read_memory(0x0010, &mut buf);
+            assert_eq!(buf[..], data[..]);
+        }
+
+        // An empty or protected slot fails the write before anything is sent
+        assert!(matches!(array.write_interleaved(&[0, 1], 0x0100, &data), Err(m95320::Error::EmptySlot(1))));
+        array.slot(2).expect("slot 2").set_protection(ProtectedArea::UpperHalf).expect("protect");
+        match array.write_interleaved(&[0, 2], 0x0800, &data) {
+            Err(m95320::Error::WriteProtected(0x0800)) => {}
+            other => panic!("expected a protected write, got {:?}", other),
+        };
+        let mut buf = [0; 40];
+        sims[0].read_memory(0x0100, &mut buf);
+        assert!(buf.iter().all(|&byte| byte == 0xFF));
+        sims[0].read_memory(0x0800, &mut buf);
+        assert!(buf.iter().all(|&byte| byte == 0xFF));
+    }
+
+    #[test]
+    fn test_rescan() {
+        let sims = [Simulator::new(), Simulator::new()];
+        sims[0].load_memory(0x0FF0, b"card A");
+        sims[1].load_memory(0x0FF0, b"card B");
+        let bus = Bus(vec![sims[0].device_without_cs(), sims[1].device_without_cs()]);
+        let mut array = FlashArray::new(bus, [sims[0].chip_select_pin(), sims[1].chip_select_pin()]).expect("probe");
+
+        for index in 0..2 {
+            array.slot(index).expect("slot").set_fingerprint_region(0x0FF0, 6).expect("set fingerprint");
+        }
+        assert_eq!(array.rescan().expect("rescan"), [false, false], "the fingerprints are kept by the array");
+
+        // A write cycle in progress is waited for, not taken as a removed card
+        array.slot(0).expect("slot 0").write_bytes(0x0010, &[1, 2, 3]).expect("write slot 0");
+        assert_eq!(array.rescan().expect("rescan"), [false, false]);
+
+        // Another card with different contents in slot 1
+        sims[1].load_memory(0x0FF0, b"card C");
+        assert_eq!(array.rescan().expect("rescan"), [false, true]);
+        assert_eq!(array.rescan().expect("rescan"), [false, false], "the new card is remembered");
+    }
+}
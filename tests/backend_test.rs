@@ -0,0 +1,99 @@
+//! These tests run storage code that is generic over the `Read` and
+//! `BlockDevice` traits against the driver and a RAM backend
+
+use m95320::prelude::*;
+use m95320::m95320::Flash;
+use m95320::sim::Simulator;
+
+/// A backend that is not an SPI chip.
+struct Ram {
+    memory: Vec<u8>,
+    page_size: usize,
+}
+
+#[derive(Debug, PartialEq)]
+struct OutOfBounds;
+
+impl Ram {
+    fn check(&self, addr: u32, len: usize) -> Result<usize, OutOfBounds> {
+        let start = addr as usize;
+        if start + len <= self.memory.len() {
+            Ok(start)
+        } else {
+            Err(OutOfBounds)
+        }
+    }
+}
+
+impl Read<u32> for Ram {
+    type Error = OutOfBounds;
+
+    fn read(&mut self, addr: u32, buf: &mut [u8]) -> Result<(), OutOfBounds> {
+        let start = self.check(addr, buf.len())?;
+        buf.copy_from_slice(&self.memory[start..start + buf.len()]);
+        Ok(())
+    }
+
+    fn capacity(&self) -> usize {
+        self.memory.len()
+    }
+}
+
+impl BlockDevice<u32> for Ram {
+    fn page_size(&self) -> usize {
+        self.page_size
+    }
+
+    fn erase_sectors(&mut self, addr: u32, amount: usize) -> Result<(), OutOfBounds> {
+        let start = self.check(addr, amount * self.page_size)?;
+        self.memory[start..start + amount * self.page_size].fill(0xFF);
+        Ok(())
+    }
+
+    fn erase_all(&mut self) -> Result<(), OutOfBounds> {
+        self.memory.fill(0xFF);
+        Ok(())
+    }
+
+    fn write_bytes(&mut self, addr: u32, data: &[u8]) -> Result<(), OutOfBounds> {
+        let start = self.check(addr, data.len())?;
+        self.memory[start..start + data.len()].copy_from_slice(data);
+        Ok(())
+    }
+}
+
+/// Appends `record` after the last page of the device, erasing it first, and
+/// reads it back.
+fn store_last_page<D: BlockDevice<u32>>(device: &mut D, record: &[u8]) -> Result<Vec<u8>, D::Error> {
+    let last_page = (device.capacity() - device.page_size()) as u32;
+    device.erase_sectors(last_page, 1)?;
+    device.write_bytes(last_page, record)?;
+
+    let mut buf = vec![0; device.page_size()];
+    device.read(last_page, &mut buf)?;
+    Ok(buf)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_generic_backends() {
+        let mut ram = Ram { memory: vec![0; 256], page_size: 16 };
+        let page = store_last_page(&mut ram, b"ram").expect("store in RAM");
+        assert_eq!(&page[..3], b"ram");
+        assert_eq!(page[3..], [0xFF; 13]);
+
+        let sim = Simulator::new();
+        sim.load_memory(0x0FE0, &[0; 32]);
+        let mut flash = Flash::init(sim.device()).unwrap();
+        assert_eq!((flash.capacity(), flash.page_size()), (4096, 32));
+        let page = store_last_page(&mut flash, b"eeprom").expect("store in EEPROM");
+        assert_eq!(&page[..6], b"eeprom");
+        assert_eq!(page[6..], [0xFF; 26]);
+
+        let mut ram = Ram { memory: vec![0; 32], page_size: 16 };
+        assert_eq!(store_last_page(&mut ram, &[0; 20]).map(|_| ()), Err(OutOfBounds), "errors of the backend");
+    }
+}
@@ -0,0 +1,213 @@
+//! These tests check how the driver addresses the other chips of the M95xxx
+//! family, by recording the bytes it sends
+
+use std::convert::Infallible;
+
+use embedded_hal::spi::{ErrorType, Operation, SpiDevice};
+use m95320::prelude::*;
+use m95320::chip::{AT25320, CAT25320, M95040, M95M01, M95M04, M95128, M95320, MC25LC320};
+use m95320::m95320::{Flash, Pins, Status};
+use m95320::sim::Simulator;
+
+/// Records the bytes written in each transaction, and the length of each
+/// operation. Reads return zeroes, so the chip always looks idle.
+#[derive(Default)]
+struct Recorder {
+    transactions: Vec<Vec<u8>>,
+    operation_lengths: Vec<Vec<usize>>,
+}
+
+impl ErrorType for Recorder {
+    type Error = Infallible;
+}
+
+impl SpiDevice for Recorder {
+    fn transaction(&mut self, operations: &mut [Operation<'_, u8>]) -> Result<(), Infallible> {
+        let mut written = Vec::new();
+        let mut lengths = Vec::new();
+        for op in operations {
+            lengths.push(match op {
+                Operation::Write(data) => data.len(),
+                Operation::Transfer(read, data) => read.len().max(data.len()),
+                Operation::TransferInPlace(data) => data.len(),
+                Operation::Read(buf) => buf.len(),
+                Operation::DelayNs(_) => 0,
+            });
+            match op {
+                Operation::Write(data) => written.extend_from_slice(data),
+                Operation::Transfer(read, data) => {
+                    written.extend_from_slice(data);
+                    read.fill(0);
+                }
+                Operation::TransferInPlace(data) => {
+                    written.extend_from_slice(data);
+                    data.fill(0);
+                }
+                Operation::Read(buf) => buf.fill(0),
+                Operation::DelayNs(_) => {}
+            }
+        }
+        self.transactions.push(written);
+        self.operation_lengths.push(lengths);
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Returns the transactions that start with `opcode`.
+    fn commands(recorder: &Recorder, opcode: u8) -> Vec<&Vec<u8>> {
+        recorder.transactions.iter().filter(|t| t[0] & !0x08 == opcode).collect()
+    }
+
+    #[test]
+    fn test_single_address_byte() {
+        let mut recorder = Recorder::default();
+        let mut flash = Flash::init_with_chip(&mut recorder, M95040).unwrap();
+
+        let mut buf = [0; 2];
+        flash.read(0x0012, &mut buf).expect("read low half");
+        flash.read(0x0112, &mut buf).expect("read high half");
+
+        let reads = commands(&recorder, 0x03);
+        assert_eq!(reads[0], &vec![0x03, 0x12]);
+        // A8 is sent in bit 3 of the instruction
+        assert_eq!(reads[1], &vec![0x0B, 0x12]);
+    }
+
+    #[test]
+    fn test_three_address_bytes() {
+        let mut recorder = Recorder::default();
+        let mut flash = Flash::init_with_chip(&mut recorder, M95M01).unwrap();
+
+        let mut buf = [0; 1];
+        flash.read(0x01_2345, &mut buf).expect("read");
+        flash.write_bytes(0x00_FFFF, &[0xAA, 0xBB]).expect("write");
+
+        assert_eq!(commands(&recorder, 0x03)[0], &vec![0x03, 0x01, 0x23, 0x45]);
+
+        // The write crosses a page boundary, so it is split in two
+        let writes = commands(&recorder, 0x02);
+        assert_eq!(writes[0], &vec![0x02, 0x00, 0xFF, 0xFF, 0xAA]);
+        assert_eq!(writes[1], &vec![0x02, 0x01, 0x00, 0x00, 0xBB]);
+    }
+
+    #[test]
+    fn test_page_size() {
+        let mut recorder = Recorder::default();
+        let mut flash = Flash::init_with_chip(&mut recorder, M95128).unwrap();
+
+        let data = [0x55; 100];
+        flash.write_bytes(0x0030, &data).expect("write");
+
+        // 64 byte pages: 16 bytes up to the boundary, then a full page, then the rest
+        let lengths: Vec<usize> = commands(&recorder, 0x02).iter().map(|t| t.len() - 3).collect();
+        assert_eq!(lengths, vec![16, 64, 20]);
+
+        let mut flash = Flash::init_with_chip(Recorder::default(), M95128).unwrap();
+        assert!(flash.write_bytes(0x4000, &[0]).is_err());
+    }
+
+    #[test]
+    fn test_vendor_status() {
+        assert_eq!(Status::from_chip::<M95320>(0xFF), Status::all());
+        assert_eq!(Status::from_chip::<CAT25320>(0x82), Status::WRITE_PROTECT_ENABLE | Status::WRITE_ENABLE_LATCH);
+        // The AT25320 reads all ones while busy
+        assert_eq!(Status::from_chip::<AT25320>(0xFF), Status::WRITE_IN_PROGRESS);
+        assert_eq!(Status::from_chip::<AT25320>(0x8C), Status::WRITE_PROTECT_ENABLE | Status::BLOCK_PROTECT);
+    }
+
+    #[test]
+    fn test_vendor_profiles() {
+        let sim = Simulator::new();
+
+        let mut flash = Flash::init_with_chip(sim.device(), MC25LC320).unwrap();
+        flash.write_bytes(0x0F00, &[1, 2, 3]).expect("write");
+        flash.flush().expect("flush");
+
+        let mut flash = Flash::init_with_chip(sim.device(), AT25320).unwrap();
+        flash.write_bytes(0x0F03, &[4, 5, 6]).expect("write");
+        flash.flush().expect("flush");
+
+        let mut flash = Flash::init_with_chip(sim.device(), CAT25320).unwrap();
+        let mut buf = [0; 6];
+        flash.read(0x0F00, &mut buf).expect("read");
+        assert_eq!(buf, [1, 2, 3, 4, 5, 6]);
+    }
+
+    #[test]
+    fn test_max_transfer_size() {
+        let mut recorder = Recorder::default();
+        let mut flash = Flash::init(&mut recorder).unwrap();
+        flash.set_max_transfer_size(100);
+
+        let mut buf = [0; 250];
+        flash.read(0x0010, &mut buf).expect("read");
+        let mut buf = [0; 1600];
+        flash.read(0x0100, &mut buf).expect("read of 16 transfers");
+
+        // More transfers than fit in one transaction need a chip select pin
+        let mut buf = [0; 2000];
+        match flash.read(0x0100, &mut buf) {
+            Err(m95320::Error::ReadTooLong { len: 2000, max: 1600 }) => {}
+            other => panic!("expected a read that is too long, got {:?}", other),
+        };
+
+        let reads: Vec<usize> = recorder.transactions.iter().enumerate()
+            .filter(|(_, t)| t[0] == 0x03)
+            .map(|(i, _)| i)
+            .collect();
+        assert_eq!(reads.len(), 2, "nothing sent for the long read");
+        assert_eq!(recorder.operation_lengths[reads[0]], vec![3, 100, 100, 50], "split within one transaction");
+        assert_eq!(recorder.operation_lengths[reads[1]], [vec![3], vec![100; 16]].concat());
+    }
+
+    #[test]
+    fn test_max_transfer_size_with_chip_select() {
+        let sim = Simulator::new();
+        let data: Vec<u8> = (0..2000).map(|i| i as u8).collect();
+        sim.load_memory(0x0100, &data);
+        let pins = Pins { chip_select: Some(sim.chip_select_pin()), ..Pins::default() };
+        let mut flash = Flash::init_with_pins(sim.device_without_cs(), pins).unwrap();
+        flash.set_max_transfer_size(100);
+
+        let mut buf = [0; 2000];
+        flash.read(0x0100, &mut buf).expect("long read");
+        assert_eq!(buf[..], data[..], "one READ with the chip selected by its pin throughout");
+
+        let mut recorder = Recorder::default();
+        {
+            let pins = Pins { chip_select: Some(sim.chip_select_pin()), ..Pins::default() };
+            let mut flash = Flash::init_with_pins(&mut recorder, pins).unwrap();
+            flash.set_max_transfer_size(100);
+            flash.read(0x0100, &mut buf).expect("long read");
+        }
+
+        let read = recorder.transactions.iter().position(|t| t.first() == Some(&0x03)).expect("READ sent");
+        assert_eq!(recorder.transactions[read], vec![0x03, 0x01, 0x00]);
+        assert_eq!(recorder.operation_lengths[read + 1..], vec![vec![100]; 20][..], "the rest are reads within the same window");
+    }
+
+    #[test]
+    fn test_max_transaction_size() {
+        let mut recorder = Recorder::default();
+        let mut flash = Flash::init_with_chip(&mut recorder, M95M04).unwrap();
+        flash.set_max_transaction_size(260);
+
+        flash.write_bytes(0x0200, &[0xAA; 512]).expect("write a page");
+        let mut buf = [0; 600];
+        flash.read(0x0400, &mut buf).expect("read");
+
+        let writes = commands(&recorder, 0x02);
+        assert_eq!(writes.iter().map(|t| t.len()).collect::<Vec<_>>(), vec![260, 260], "page split into two WRITEs");
+        assert_eq!(writes[1][..4], [0x02, 0x00, 0x03, 0x00], "second half of the page");
+
+        let reads: Vec<usize> = recorder.transactions.iter().enumerate()
+            .filter(|(_, t)| t[0] == 0x03)
+            .map(|(i, _)| recorder.operation_lengths[i].iter().sum())
+            .collect();
+        assert_eq!(reads, vec![260, 260, 4 + 88]);
+    }
+}
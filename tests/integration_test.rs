@@ -6,6 +6,7 @@ use rppal::spi::{Bus, Mode, SlaveSelect, Spi};
 
 use m95320::prelude::*;
 use m95320::m95320::Flash;
+use m95320::compat::LegacyDevice;
 use port_expander::{ Pca9555 };
 use std::collections::HashMap;
 
@@ -20,12 +21,13 @@ mod tests {
     use super::*;
 
     #[test]
+    #[ignore = "needs a Raspberry Pi wired to an M95320"]
     fn test() {
         let gpio = Gpio::new().unwrap();
         let cs = gpio.get(GPIO_MEMORY_CHIP_SELECT).unwrap().into_output();
         let spi = Spi::new(Bus::Spi0, SlaveSelect::Ss0, 10_000_000, Mode::Mode0).unwrap();
 
-        let mut flash = Flash::init(spi, cs).unwrap();
+        let mut flash = Flash::init(LegacyDevice::new(spi, cs).unwrap()).unwrap();
 
         let _status = flash.read_status().expect("get status");
 
@@ -35,20 +37,21 @@ mod tests {
 
 
         flash.read(0, &mut page_buffer).expect("read");
-        assert_eq!(page_buffer, [0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0]);
+        assert_eq!(page_buffer, [0xFF; 32], "erased");
 
         flash.read(5, &mut page_buffer).expect("read");
-        assert_eq!(page_buffer, [0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0]);
+        assert_eq!(page_buffer, [0xFF; 32], "erased");
 
         flash.read(32, &mut page_buffer).expect("read");
-        assert_eq!(page_buffer, [0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0]);
+        assert_eq!(page_buffer, [0xFF; 32], "erased");
 
         let hello = String::from("hello memory!");
+        let mut page_buffer: [u8; 32] = [0x0; 32];
         for (i, byte) in hello.as_bytes().into_iter().enumerate() {
             page_buffer[i] = byte.clone();
         }
 
-        flash.write_bytes(0, &mut page_buffer).expect("write");
+        flash.write_bytes(0, &page_buffer).expect("write");
         flash.read(0, &mut page_buffer).expect("read");
         assert_eq!(page_buffer, [104, 101, 108, 108, 111, 32, 109, 101, 109, 111, 114, 121, 33, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0], "simple write and read of first page");
 
@@ -57,12 +60,12 @@ mod tests {
             page_buffer[i] = byte.clone();
         } 
 
-        flash.write_bytes(30, &mut page_buffer).expect("write");
+        flash.write_bytes(30, &page_buffer).expect("write");
         flash.read(0, &mut page_buffer).expect("read");
         assert_eq!(page_buffer, [104, 101, 108, 108, 111, 32, 109, 101, 109, 111, 114, 121, 33, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 104, 101], "write at address straddling page 0 and page 1, read page 1");
 
         flash.read(32, &mut page_buffer).expect("read");
-        assert_eq!(page_buffer, [108, 108, 111, 32, 109, 101, 109, 111, 114, 121, 33, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0], "write at address straddling page 0 and page 1, read page 2");
+        assert_eq!(page_buffer, [108, 108, 111, 32, 109, 101, 109, 111, 114, 121, 33, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0xFF, 0xFF], "write at address straddling page 0 and page 1, read page 2");
 
         flash.read(5, &mut page_buffer).expect("read");
         assert_eq!(page_buffer, [32, 109, 101, 109, 111, 114, 121, 33, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 104, 101, 108, 108, 111, 32, 109], "read straddling page boundary");
@@ -72,7 +75,7 @@ mod tests {
         for (i, byte) in chuckwudi.as_bytes().into_iter().enumerate() {
             small_buffer[i] = byte.clone();
         }
-        flash.write_bytes(0, &mut small_buffer).expect("write");
+        flash.write_bytes(0, &small_buffer).expect("write");
         flash.read(0, &mut small_buffer).expect("read");
         assert_eq!(small_buffer, [0x63, 0x68, 0x75, 0x63, 0x6b, 0x77, 0x75, 0x64, 0x69], "write and read a buffer smaller than a page");
 
@@ -84,7 +87,7 @@ mod tests {
         for (i, byte) in chuckwudi.as_bytes().into_iter().enumerate() {
             small_buffer[i] = byte.clone();
         }
-        flash.write_bytes(30, &mut small_buffer).expect("write");
+        flash.write_bytes(30, &small_buffer).expect("write");
         flash.read(30, &mut small_buffer).expect("read");
         assert_eq!(small_buffer, [0x63, 0x68, 0x75, 0x63, 0x6b, 0x77, 0x75, 0x64, 0x69], "write and read a buffer smaller than a page at page boundary");  
     
@@ -104,6 +107,7 @@ mod tests {
     }
 
     #[test]
+    #[ignore = "needs a Raspberry Pi with the wyldcard plinth"]
     fn testWyldcard() {
 
         // wyldcard prototype plinth setup
@@ -118,8 +122,7 @@ mod tests {
         let memory_chip_select = virtual_gpios.io0_7.into_output().expect("");
 
         let mut flash = Flash::init(
-                    spi,
-                    memory_chip_select,
+                    LegacyDevice::new(spi, memory_chip_select).expect("chip select"),
                   ).expect("memory");
 
         ////////////////////////////////////////////////
@@ -132,20 +135,21 @@ mod tests {
 
 
         flash.read(0, &mut page_buffer).expect("read");
-        assert_eq!(page_buffer, [0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0]);
+        assert_eq!(page_buffer, [0xFF; 32], "erased");
 
         flash.read(5, &mut page_buffer).expect("read");
-        assert_eq!(page_buffer, [0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0]);
+        assert_eq!(page_buffer, [0xFF; 32], "erased");
 
         flash.read(32, &mut page_buffer).expect("read");
-        assert_eq!(page_buffer, [0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0]);
+        assert_eq!(page_buffer, [0xFF; 32], "erased");
 
         let hello = String::from("hello memory!");
+        let mut page_buffer: [u8; 32] = [0x0; 32];
         for (i, byte) in hello.as_bytes().into_iter().enumerate() {
             page_buffer[i] = byte.clone();
         }
 
-        flash.write_bytes(0, &mut page_buffer).expect("write");
+        flash.write_bytes(0, &page_buffer).expect("write");
         flash.read(0, &mut page_buffer).expect("read");
         assert_eq!(page_buffer, [104, 101, 108, 108, 111, 32, 109, 101, 109, 111, 114, 121, 33, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0], "simple write and read of first page");
 
@@ -154,12 +158,12 @@ mod tests {
             page_buffer[i] = byte.clone();
         } 
 
-        flash.write_bytes(30, &mut page_buffer).expect("write");
+        flash.write_bytes(30, &page_buffer).expect("write");
         flash.read(0, &mut page_buffer).expect("read");
         assert_eq!(page_buffer, [104, 101, 108, 108, 111, 32, 109, 101, 109, 111, 114, 121, 33, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 104, 101], "write at address straddling page 0 and page 1, read page 1");
 
         flash.read(32, &mut page_buffer).expect("read");
-        assert_eq!(page_buffer, [108, 108, 111, 32, 109, 101, 109, 111, 114, 121, 33, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0], "write at address straddling page 0 and page 1, read page 2");
+        assert_eq!(page_buffer, [108, 108, 111, 32, 109, 101, 109, 111, 114, 121, 33, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0xFF, 0xFF], "write at address straddling page 0 and page 1, read page 2");
 
         flash.read(5, &mut page_buffer).expect("read");
         assert_eq!(page_buffer, [32, 109, 101, 109, 111, 114, 121, 33, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 104, 101, 108, 108, 111, 32, 109], "read straddling page boundary");
@@ -170,7 +174,7 @@ mod tests {
         for (i, byte) in chuckwudi.as_bytes().into_iter().enumerate() {
             small_buffer[i] = byte.clone();
         }
-        flash.write_bytes(0, &mut small_buffer).expect("write");
+        flash.write_bytes(0, &small_buffer).expect("write");
         flash.read(0, &mut small_buffer).expect("read");
         assert_eq!(small_buffer, [0x63, 0x68, 0x75, 0x63, 0x6b, 0x77, 0x75, 0x64, 0x69], "write and read a buffer smaller than a page");
 
@@ -182,7 +186,7 @@ mod tests {
         for (i, byte) in chuckwudi.as_bytes().into_iter().enumerate() {
             small_buffer[i] = byte.clone();
         }
-        flash.write_bytes(30, &mut small_buffer).expect("write");
+        flash.write_bytes(30, &small_buffer).expect("write");
         flash.read(30, &mut small_buffer).expect("read");
         assert_eq!(small_buffer, [0x63, 0x68, 0x75, 0x63, 0x6b, 0x77, 0x75, 0x64, 0x69], "write and read a buffer smaller than a page at page boundary");
 
@@ -0,0 +1,72 @@
+//! These tests keep a map and a queue in the simulated chip through the
+//! `sequential-storage` integration
+
+use embassy_futures::block_on;
+use m95320::m95320::Flash;
+use m95320::sequential::sequential_storage::queue::QueueStorage;
+use m95320::sim::Simulator;
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_map() {
+        let sim = Simulator::new();
+        let mut flash = Flash::init(sim.device()).unwrap();
+        let mut buf = [0; 16];
+
+        {
+            let mut settings = flash.map::<u8>(0x0C00..0x1000).expect("map");
+            for key in 0..40u8 {
+                block_on(settings.store_item(&mut buf, &key, &u32::from(key))).expect("store");
+            }
+            // Updating a setting many times goes around all pages of the range
+            for value in 0..200u32 {
+                block_on(settings.store_item(&mut buf, &7, &value)).expect("update");
+            }
+        }
+
+        let mut page = [0; 32];
+        sim.read_memory(0x0000, &mut page);
+        assert_eq!(page, [0xFF; 32], "nothing is written outside the range");
+
+        // Another map on the same range finds the items again, eg. after a reset
+        let mut settings = flash.map::<u8>(0x0C00..0x1000).expect("map");
+        assert_eq!(block_on(settings.fetch_item::<u32>(&mut buf, &7)).expect("fetch"), Some(199));
+        for key in (0..40u8).filter(|&key| key != 7) {
+            assert_eq!(block_on(settings.fetch_item::<u32>(&mut buf, &key)).expect("fetch"), Some(u32::from(key)));
+        }
+        assert_eq!(block_on(settings.fetch_item::<u32>(&mut buf, &40)).expect("fetch"), None);
+    }
+
+    #[test]
+    fn test_queue() {
+        let sim = Simulator::new();
+        let mut flash = Flash::init(sim.device()).unwrap();
+
+        let mut queue = flash.queue(0x0000..0x0100).expect("queue");
+        for item in [&b"first"[..], b"second", b"third"].iter() {
+            block_on(queue.push(item, false)).expect("push");
+        }
+        let mut buf = [0; 16];
+        assert_eq!(block_on(queue.pop(&mut buf)).expect("pop").as_deref(), Some(&b"first"[..]));
+        let (flash, _) = QueueStorage::destroy(queue);
+
+        let mut queue = flash.queue(0x0000..0x0100).expect("queue");
+        assert_eq!(block_on(queue.pop(&mut buf)).expect("pop").as_deref(), Some(&b"second"[..]));
+        assert_eq!(block_on(queue.pop(&mut buf)).expect("pop").as_deref(), Some(&b"third"[..]));
+        assert_eq!(block_on(queue.pop(&mut buf)).expect("pop"), None);
+    }
+
+    #[test]
+    fn test_range() {
+        let sim = Simulator::new();
+        let mut flash = Flash::init(sim.device()).unwrap();
+
+        assert!(matches!(flash.map::<u8>(0x0010..0x0100), Err(m95320::Error::NotPageAligned(0x0010))));
+        assert!(matches!(flash.map::<u8>(0x0F00..0x1100), Err(m95320::Error::AddressOutOfBounds { start: 0x0F00, len: 0x0200 })));
+        assert!(matches!(flash.queue(0x0100..0x0100), Err(m95320::Error::AddressOutOfBounds { start: 0x0100, len: 0 })));
+        assert!(matches!(flash.queue(0x0100..0x0120), Err(m95320::Error::RangeTooSmall { start: 0x0100, end: 0x0120 })));
+    }
+}
@@ -0,0 +1,851 @@
+//! These tests run the driver against the in-memory simulator from the
+//! `sim` feature, so they don't need any hardware
+
+use m95320::prelude::*;
+use m95320::m95320::{Flash, PermanentLock, Pins, PollMode, ProtectedArea, Status, WrapPolicy};
+use m95320::chip::{AT25320, M95320};
+use m95320::sim::Simulator;
+use m95320::compat::LegacyDevice;
+use m95320::asynch;
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test() {
+        let sim = Simulator::new();
+        sim.load_memory(0, &[0xAA; 96]);
+
+        let mut flash = Flash::init(sim.device()).unwrap();
+
+        let _status = flash.read_status().expect("get status");
+
+        let mut page_buffer: [u8; 32] = [0x0; 32];
+
+        flash.erase_sectors(0, 2).expect("erase");
+
+
+        flash.read(0, &mut page_buffer).expect("read");
+        assert_eq!(page_buffer, [0xFF; 32], "erased");
+
+        flash.read(5, &mut page_buffer).expect("read");
+        assert_eq!(page_buffer, [0xFF; 32], "erased");
+
+        flash.read(32, &mut page_buffer).expect("read");
+        assert_eq!(page_buffer, [0xFF; 32], "erased");
+
+        let hello = String::from("hello memory!");
+        let mut page_buffer: [u8; 32] = [0x0; 32];
+        for (i, byte) in hello.as_bytes().iter().enumerate() {
+            page_buffer[i] = *byte;
+        }
+
+        flash.write_bytes(0, &page_buffer).expect("write");
+        flash.read(0, &mut page_buffer).expect("read");
+        assert_eq!(page_buffer, [104, 101, 108, 108, 111, 32, 109, 101, 109, 111, 114, 121, 33, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0], "simple write and read of first page");
+
+        let mut page_buffer: [u8; 32] = [0x0; 32];
+        for (i, byte) in hello.as_bytes().iter().enumerate() {
+            page_buffer[i] = *byte;
+        }
+
+        flash.write_bytes(30, &page_buffer).expect("write");
+        flash.read(0, &mut page_buffer).expect("read");
+        assert_eq!(page_buffer, [104, 101, 108, 108, 111, 32, 109, 101, 109, 111, 114, 121, 33, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 104, 101], "write at address straddling page 0 and page 1, read page 1");
+
+        flash.read(32, &mut page_buffer).expect("read");
+        assert_eq!(page_buffer, [108, 108, 111, 32, 109, 101, 109, 111, 114, 121, 33, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0xFF, 0xFF], "write at address straddling page 0 and page 1, read page 2");
+
+        flash.read(5, &mut page_buffer).expect("read");
+        assert_eq!(page_buffer, [32, 109, 101, 109, 111, 114, 121, 33, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 104, 101, 108, 108, 111, 32, 109], "read straddling page boundary");
+
+        let chuckwudi = String::from("chuckwudi");
+        let mut small_buffer: [u8; 9] = [0x0; 9];
+        for (i, byte) in chuckwudi.as_bytes().iter().enumerate() {
+            small_buffer[i] = *byte;
+        }
+        flash.write_bytes(0, &small_buffer).expect("write");
+        flash.read(0, &mut small_buffer).expect("read");
+        assert_eq!(small_buffer, [0x63, 0x68, 0x75, 0x63, 0x6b, 0x77, 0x75, 0x64, 0x69], "write and read a buffer smaller than a page");
+
+        flash.read(1, &mut small_buffer).expect("read");
+        assert_eq!(small_buffer, [0x68, 0x75, 0x63, 0x6b, 0x77, 0x75, 0x64, 0x69, 111], "write and read a buffer smaller than a page, offset 1");
+
+
+        let mut small_buffer: [u8; 9] = [0x0; 9];
+        for (i, byte) in chuckwudi.as_bytes().iter().enumerate() {
+            small_buffer[i] = *byte;
+        }
+        flash.write_bytes(30, &small_buffer).expect("write");
+        flash.read(30, &mut small_buffer).expect("read");
+        assert_eq!(small_buffer, [0x63, 0x68, 0x75, 0x63, 0x6b, 0x77, 0x75, 0x64, 0x69], "write and read a buffer smaller than a page at page boundary");
+
+        // test Write Enable Latch
+        let status = flash.read_status().expect("get status");
+        assert_eq!(status.bits(), 0x0, "status starts clear");
+
+        flash._write_enable().expect("set write enable latch");
+
+        let status = flash.read_status().expect("get status");
+        assert_eq!(status, Status::WRITE_ENABLE_LATCH, "write enable latch flag is set");
+
+        flash._write_disable().expect("unset write enable latch");
+
+        let status = flash.read_status().expect("get status");
+        assert_eq!(status.bits(), 0x0, "write enable latch flag is not set");
+    }
+
+    #[test]
+    fn test_simulator() {
+        let sim = Simulator::new();
+        let (mut spi, mut cs) = sim.split();
+
+        use embedded_hal_02::blocking::spi::Transfer;
+        use embedded_hal_02::digital::v2::OutputPin;
+
+        let mut command = |bytes: &mut [u8]| {
+            cs.set_low().unwrap();
+            spi.transfer(bytes).unwrap();
+            cs.set_high().unwrap();
+        };
+
+        // WRITE is ignored without the Write Enable Latch
+        command(&mut [0x02, 0x00, 0x00, 0xAA]);
+        let mut byte = [0];
+        sim.read_memory(0, &mut byte);
+        assert_eq!(byte, [0xFF], "write without WREN is ignored");
+
+        // data past the end of the page wraps to the start of the same page
+        command(&mut [0x06]);
+        command(&mut [0x02, 0x00, 0x3E, 1, 2, 3, 4]);
+        let mut page = [0; 32];
+        sim.read_memory(0x20, &mut page);
+        assert_eq!(&page[..2], &[3, 4], "write wraps around within the page");
+        assert_eq!(&page[30..], &[1, 2], "write starts at the addressed byte");
+        assert_eq!(sim.status(), Status::WRITE_IN_PROGRESS, "write cycle started, WEL reset");
+
+        // only RDSR is accepted during the write cycle
+        command(&mut [0x06]);
+        let mut rdsr = [0x05, 0, 0, 0, 0];
+        command(&mut rdsr);
+        assert_eq!(rdsr[1..], [0x01, 0x01, 0x01, 0x00], "WIP clears after the write cycle, WREN was ignored");
+
+        // READ rolls over from the last address to 0x000
+        sim.load_memory(0x0FFF, &[0x42]);
+        sim.load_memory(0x0000, &[0x43]);
+        let mut read = [0x03, 0x0F, 0xFF, 0, 0];
+        command(&mut read);
+        assert_eq!(read[3..], [0x42, 0x43], "read rolls over at the end of memory");
+
+        // writes into a block protected area are ignored
+        sim.set_write_cycle_polls(0);
+        command(&mut [0x06]);
+        command(&mut [0x01, 0b0000_0100]);
+        command(&mut [0x06]);
+        command(&mut [0x02, 0x0C, 0x00, 0x55]);
+        sim.read_memory(0x0C00, &mut byte);
+        assert_eq!(byte, [0xFF], "upper quarter is protected");
+        command(&mut [0x06]);
+        command(&mut [0x02, 0x0B, 0xFF, 0x55]);
+        sim.read_memory(0x0BFF, &mut byte);
+        assert_eq!(byte, [0x55], "lower three quarters are writable");
+    }
+
+    #[test]
+    fn test_legacy_device() {
+        let sim = Simulator::new();
+        let (spi, cs) = sim.split();
+
+        let mut flash = Flash::init(LegacyDevice::new(spi, cs).unwrap()).unwrap();
+
+        let hello = *b"hello legacy!";
+        flash.write_bytes(0x1F, &hello).expect("write");
+        assert_eq!(hello, *b"hello legacy!", "written data is left untouched");
+
+        let mut buf = [0; 13];
+        flash.read(0x1F, &mut buf).expect("read");
+        assert_eq!(&buf, b"hello legacy!", "write and read through an embedded-hal 0.2 bus");
+
+        const GREETING: &[u8] = b"hello from flash!";
+        flash.write_bytes(0x100, GREETING).expect("write from a constant");
+        let mut buf = [0; 17];
+        flash.read(0x100, &mut buf).expect("read");
+        assert_eq!(&buf[..], GREETING);
+
+        use embedded_hal::spi::{Operation, SpiDevice};
+        let sim = Simulator::new();
+        let (spi, cs) = sim.split();
+        let mut device = LegacyDevice::new(spi, cs).unwrap();
+        let mut status = [0];
+        let mut operations = [Operation::Write(&[0x05]), Operation::DelayNs(100), Operation::Read(&mut status)];
+        match device.transaction(&mut operations) {
+            Err(m95320::compat::LegacyError::Unsupported) => {}
+            other => panic!("expected an unsupported operation, got {:?}", other),
+        };
+        assert_eq!(status, [0], "rejected before anything is sent");
+    }
+
+    #[test]
+    fn test_single_transfer() {
+        use embedded_hal_02::blocking::spi::Transfer;
+        use embedded_hal_02::digital::v2::OutputPin;
+
+        /// A bus that selects the chip for the duration of each transfer, like
+        /// spidev with a kernel-managed chip-select.
+        struct HardwareCs<SPI, CS>(SPI, CS);
+
+        impl<SPI: Transfer<u8>, CS: OutputPin> Transfer<u8> for HardwareCs<SPI, CS> {
+            type Error = SPI::Error;
+
+            fn transfer<'w>(&mut self, words: &'w mut [u8]) -> Result<&'w [u8], Self::Error> {
+                self.1.set_low().ok();
+                let result = self.0.transfer(words);
+                self.1.set_high().ok();
+                result
+            }
+        }
+
+        let sim = Simulator::new();
+        let (spi, cs) = sim.split();
+
+        let mut flash = Flash::init(LegacyDevice::new_single_transfer(HardwareCs(spi, cs))).unwrap();
+
+        let hello = *b"hello hardware chip-select!";
+        flash.write_bytes(0x1F, &hello).expect("write");
+
+        let mut buf = [0; 27];
+        flash.read(0x1F, &mut buf).expect("read");
+        assert_eq!(&buf, b"hello hardware chip-select!", "command and data sent in one transfer");
+
+        let mut too_long = [0; 600];
+        match flash.read(0, &mut too_long) {
+            Err(m95320::Error::Spi(m95320::compat::LegacyError::TooLong)) => {}
+            other => panic!("expected a too long transaction, got {:?}", other),
+        };
+
+        let sim = Simulator::new();
+        let (spi, cs) = sim.split();
+        let mut flash = Flash::init_single_transfer(HardwareCs(spi, cs)).unwrap();
+
+        let data: Vec<u8> = (0..2000).map(|i| i as u8).collect();
+        flash.write_bytes(0x0010, &data).expect("long write");
+        let mut buf = vec![0; 2000];
+        flash.read(0x0010, &mut buf).expect("long read");
+        assert_eq!(buf, data, "split into transactions that fit");
+    }
+
+    #[test]
+    fn test_async() {
+        struct CountingDelay(u32);
+
+        impl embedded_hal_async::delay::DelayNs for CountingDelay {
+            async fn delay_ns(&mut self, _ns: u32) {
+                self.0 += 1;
+            }
+        }
+
+        let sim = Simulator::new();
+        sim.load_memory(0, &[0xAA; 64]);
+        sim.set_write_cycle_polls(2);
+
+        embassy_futures::block_on(async {
+            let mut flash = asynch::Flash::init(sim.device(), CountingDelay(0)).await.unwrap();
+
+            flash.erase_sectors(0, 2).await.expect("erase");
+            let mut page_buffer = [0; 32];
+            flash.read(16, &mut page_buffer).await.expect("read");
+            assert_eq!(page_buffer, [0xFF; 32], "erase first two pages");
+
+            flash.write_bytes(30, b"hello async memory!").await.expect("write");
+            let mut buf = [0; 19];
+            flash.read(30, &mut buf).await.expect("read");
+            assert_eq!(&buf, b"hello async memory!", "write straddling page 0 and page 1");
+
+            assert_eq!(flash.read_status().await.expect("get status").bits(), 0x0, "status is clear after writing");
+
+            let (_, delay) = flash.release();
+            assert_eq!(delay.0, 4 * 2, "two delays while each of the four pages is written");
+        });
+    }
+
+    #[test]
+    fn test_async_protect() {
+        struct NoDelay;
+
+        impl embedded_hal_async::delay::DelayNs for NoDelay {
+            async fn delay_ns(&mut self, _ns: u32) {}
+        }
+
+        let sim = Simulator::new();
+        Flash::init(sim.device()).unwrap().set_protection(ProtectedArea::UpperQuarter).expect("protect");
+
+        embassy_futures::block_on(async {
+            let mut flash = asynch::Flash::init(sim.device(), NoDelay).await.unwrap();
+            assert_eq!(flash.protected_area().await.expect("get protection"), ProtectedArea::UpperQuarter);
+
+            match flash.write_bytes(0x0BF0, &[0xAA; 32]).await {
+                Err(m95320::Error::WriteProtected(0x0BF0)) => {}
+                other => panic!("expected a protected write, got {:?}", other),
+            };
+            match flash.erase_all().await {
+                Err(m95320::Error::WriteProtected(0x0000)) => {}
+                other => panic!("expected a protected erase, got {:?}", other),
+            };
+        });
+
+        let mut buf = [0; 32];
+        sim.read_memory(0x0BF0, &mut buf);
+        assert_eq!(buf, [0xFF; 32], "nothing was written before the protected area");
+    }
+
+    #[test]
+    fn test_write_timeout() {
+        struct CountingDelay(u32);
+
+        impl embedded_hal::delay::DelayNs for CountingDelay {
+            fn delay_ns(&mut self, _ns: u32) {
+                self.0 += 1;
+            }
+        }
+
+        let sim = Simulator::new();
+        sim.set_write_cycle_polls(3);
+
+        let mut flash = Flash::init(sim.device()).unwrap().with_delay(CountingDelay(0));
+        assert_eq!(flash.last_write_cycle_us(), None);
+
+        flash.write_bytes(0, &[1, 2, 3]).expect("write");
+        flash.flush().expect("flush");
+        assert_eq!(flash.last_write_cycle_us(), Some(300), "three polls 100 us apart");
+
+        // A chip that never finishes its write cycle
+        sim.set_write_cycle_polls(255);
+        flash.set_poll_interval_us(1_000);
+        flash.write_bytes(0, &[4]).expect("write returns before the write cycle");
+        match flash.flush() {
+            Err(m95320::Error::Timeout(elapsed_us)) => assert_eq!(elapsed_us, 8_000, "tW max plus 50%, rounded up to the poll interval"),
+            other => panic!("expected a timeout, got {:?}", other),
+        };
+    }
+
+    #[test]
+    fn test_block_protect() {
+        let sim = Simulator::new();
+        let mut flash = Flash::init(sim.device()).unwrap();
+
+        assert_eq!(flash.protected_area().expect("get protection"), ProtectedArea::None, "fresh chip is unprotected");
+
+        flash.set_protection(ProtectedArea::UpperHalf).expect("protect upper half");
+        assert_eq!(sim.status(), Status::from_bits_truncate(0b1000), "BP1 is set");
+        assert_eq!(flash.protected_area().expect("get protection").address_range::<M95320>(), 0x0800..0x1000);
+
+        match flash.write_bytes(0x07F0, &[0xAA; 32]) {
+            Err(m95320::Error::WriteProtected(0x07F0)) => {}
+            other => panic!("write into protected area: {:?}", other),
+        }
+        let mut buf = [0; 32];
+        sim.read_memory(0x07F0, &mut buf);
+        assert_eq!(buf, [0xFF; 32], "nothing was written before failing");
+
+        flash.write_bytes(0x07E0, &[0xAA; 32]).expect("write below protected area");
+
+        flash.set_protection(ProtectedArea::None).expect("unprotect");
+        assert_eq!(ProtectedArea::None.address_range::<M95320>().len(), 0);
+        flash.write_bytes(0x0FE0, &[0xAA; 32]).expect("write after unprotecting");
+    }
+
+    #[test]
+    fn test_protected_erase() {
+        let sim = Simulator::new();
+        sim.load_memory(0, &[0xAA; 0x1000]);
+        let mut flash = Flash::init(sim.device()).unwrap();
+        flash.set_protection(ProtectedArea::UpperQuarter).expect("protect upper quarter");
+
+        match flash.erase_all() {
+            Err(m95320::Error::WriteProtected(0x0000)) => {}
+            other => panic!("expected a protected erase, got {:?}", other),
+        };
+        match flash.erase_sectors(0x0BE0, 2) {
+            Err(m95320::Error::WriteProtected(0x0BE0)) => {}
+            other => panic!("expected a protected erase, got {:?}", other),
+        };
+        flash.flush().expect("flush");
+
+        let mut memory = [0; 0x1000];
+        sim.read_memory(0, &mut memory);
+        assert!(memory.iter().all(|&byte| byte == 0xAA), "nothing was erased before failing");
+    }
+
+    #[test]
+    fn test_protect_after_write() {
+        // The AT25320 only reports WIP while a write cycle is in progress
+        let sim = Simulator::new();
+        sim.set_write_cycle_polls(2);
+        let mut flash = Flash::init_with_chip(sim.device(), AT25320).unwrap();
+
+        flash.set_protection(ProtectedArea::UpperHalf).expect("protect upper half");
+        flash.write_bytes(0x0000, &[1]).expect("write");
+        flash.set_status_register_write_disable(true).expect("set SRWD");
+        assert_eq!(sim.status(), Status::from_bits_truncate(0b1000_1000), "BP1 is kept");
+
+        flash.write_bytes(0x0000, &[2]).expect("write");
+        assert_eq!(flash.protected_area().expect("get protection"), ProtectedArea::UpperHalf);
+        flash.write_bytes(0x0000, &[3]).expect("write");
+        flash.set_protection(ProtectedArea::All).expect("protect all");
+        assert_eq!(sim.status(), Status::from_bits_truncate(0b1000_1100), "SRWD is kept");
+    }
+
+    #[test]
+    fn test_hardware_write_protect() {
+        let sim = Simulator::new();
+        let mut flash = Flash::init_with_write_protect(sim.device(), sim.write_protect_pin()).unwrap();
+
+        flash.set_protection(ProtectedArea::All).expect("protect everything");
+        flash.lock_status_register().expect("lock");
+        assert!(flash.is_hardware_protected().expect("get protection mode"), "in hardware protected mode");
+        assert!(sim.status().contains(Status::STATUS_REGISTER_WRITE_DISABLE), "SRWD is set");
+
+        match flash.set_protection(ProtectedArea::None) {
+            Err(m95320::Error::UnexpectedStatus) => {}
+            other => panic!("change protection while locked: {:?}", other),
+        }
+        assert_eq!(sim.status().protected_area(), ProtectedArea::All, "status register is locked");
+
+        flash.set_write_protect(false).expect("release W");
+        assert!(!flash.is_hardware_protected().expect("get protection mode"), "W high leaves hardware protected mode");
+
+        flash.unlock_status_register().expect("unlock");
+        flash.set_protection(ProtectedArea::None).expect("unprotect");
+        assert_eq!(sim.status().bits(), 0x0, "status register is clear");
+
+        let mut flash = Flash::init(sim.device()).unwrap();
+        match flash.lock_status_register() {
+            Err(m95320::Error::MissingPin) => {}
+            other => panic!("lock without W pin: {:?}", other),
+        }
+        assert_eq!(sim.status().bits(), 0x0, "nothing is written without W pin");
+    }
+
+    #[test]
+    fn test_held_read() {
+        use embedded_hal::spi::SpiDevice;
+
+        let sim = Simulator::new();
+        let mut data = [0; 96];
+        for (i, byte) in data.iter_mut().enumerate() {
+            *byte = i as u8;
+        }
+        sim.load_memory(0x0FD0, &data[..48]);
+        sim.load_memory(0x0000, &data[48..]);
+
+        let pins = Pins {
+            hold: Some(sim.hold_pin()),
+            chip_select: Some(sim.chip_select_pin()),
+            ..Pins::default()
+        };
+        let mut flash = Flash::init_with_pins(sim.device_without_cs(), pins).unwrap();
+        flash.set_wrap_policy(WrapPolicy::Wrap);
+        let mut display = sim.device_without_cs();
+
+        let mut read = flash.start_read(0x0FD0).expect("start read");
+        let mut chunk = [0; 32];
+        read.read(&mut chunk).expect("read first chunk");
+        assert_eq!(chunk[..], data[..32], "first chunk");
+
+        read.hold().expect("hold");
+        display.write(&[0x06, 0x02, 0x00, 0x00, 0xAA]).unwrap();
+        read.resume().expect("resume");
+
+        read.read(&mut chunk).expect("read second chunk");
+        assert_eq!(chunk[..], data[32..64], "second chunk continues after hold, rolling over at the end of memory");
+
+        read.hold().expect("hold");
+        display.write(&[0x00; 8]).unwrap();
+        read.read(&mut chunk).expect("read third chunk");
+        assert_eq!(chunk[..], data[64..], "reading resumes a held transfer");
+        assert_eq!(read.address(), 0x0030);
+        read.finish().expect("finish");
+
+        let mut byte = [0];
+        flash.read(0, &mut byte).expect("read");
+        assert_eq!(byte[0], 48, "bus traffic during hold was ignored");
+
+        match flash.start_read(0x1000) {
+            Err(m95320::Error::AddressOutOfBounds { start: 0x1000, len: 0 }) => {}
+            other => panic!("start read past the end of memory: {:?}", other.map(|_| ())),
+        };
+
+        let mut flash = Flash::init(sim.device()).unwrap();
+        match flash.start_read(0) {
+            Err(m95320::Error::MissingPin) => {}
+            other => panic!("start read without chip select pin: {:?}", other.map(|_| ())),
+        };
+    }
+
+    #[test]
+    fn test_id_page() {
+        let sim = Simulator::new();
+        let mut flash = Flash::init(sim.device()).unwrap();
+
+        assert!(!flash.id_page_locked().expect("get lock status"), "fresh chip is unlocked");
+
+        flash.write_id_page(4, b"SN-00042").expect("write serial number");
+        let mut serial = [0; 8];
+        flash.read_id_page(4, &mut serial).expect("read serial number");
+        assert_eq!(&serial, b"SN-00042", "write and read the identification page");
+
+        let mut page = [0; 32];
+        flash.read(0, &mut page).expect("read");
+        assert_eq!(page, [0xFF; 32], "memory array is untouched");
+
+        match flash.read_id_page(30, &mut serial) {
+            Err(m95320::Error::AddressOutOfBounds { start: 30, len: 8 }) => {}
+            other => panic!("read past the identification page: {:?}", other),
+        }
+
+        flash.lock_id_page(PermanentLock::i_understand_this_cannot_be_undone()).expect("lock");
+        assert!(flash.id_page_locked().expect("get lock status"), "identification page is locked");
+
+        match flash.write_id_page(4, b"SN-99999") {
+            Err(m95320::Error::IdPageLocked) => {}
+            other => panic!("write locked identification page: {:?}", other),
+        }
+        flash.read_id_page(4, &mut serial).expect("read serial number");
+        assert_eq!(&serial, b"SN-00042", "locked identification page is unchanged");
+    }
+
+    #[test]
+    fn test_continuous_polling() {
+        use embedded_hal::digital::{ErrorType, OutputPin};
+        use std::cell::Cell;
+        use std::rc::Rc;
+
+        /// Counts how often the chip is selected.
+        struct CountingPin<P>(P, Rc<Cell<u32>>);
+
+        impl<P: OutputPin> ErrorType for CountingPin<P> {
+            type Error = P::Error;
+        }
+
+        impl<P: OutputPin> OutputPin for CountingPin<P> {
+            fn set_low(&mut self) -> Result<(), Self::Error> {
+                self.1.set(self.1.get() + 1);
+                self.0.set_low()
+            }
+
+            fn set_high(&mut self) -> Result<(), Self::Error> {
+                self.0.set_high()
+            }
+        }
+
+        let sim = Simulator::new();
+        sim.set_write_cycle_polls(3);
+
+        let mut flash = Flash::init(sim.device()).unwrap();
+        match flash.set_poll_mode(PollMode::Continuous) {
+            Err(m95320::Error::MissingPin) => {}
+            other => panic!("expected a missing pin, got {:?}", other),
+        };
+
+        let selects = Rc::new(Cell::new(0));
+        let pins = Pins { chip_select: Some(CountingPin(sim.chip_select_pin(), selects.clone())), ..Pins::default() };
+        let mut flash = Flash::init_with_pins(sim.device_without_cs(), pins).unwrap();
+
+        selects.set(0);
+        flash.write_bytes(0, &[1, 2, 3]).expect("write");
+        flash.flush().expect("flush");
+        assert_eq!(selects.get(), 2 + 4, "WREN, WRITE, then RDSR until WIP clears");
+
+        flash.set_poll_mode(PollMode::Continuous).expect("set poll mode");
+        selects.set(0);
+        flash.write_bytes(3, &[4, 5, 6]).expect("write");
+        flash.flush().expect("flush");
+        assert_eq!(selects.get(), 2 + 1, "WREN, WRITE, then a single RDSR");
+
+        let mut buf = [0; 6];
+        flash.read(0, &mut buf).expect("read");
+        assert_eq!(buf, [1, 2, 3, 4, 5, 6]);
+    }
+
+    #[test]
+    fn test_bounds() {
+        let sim = Simulator::new();
+        sim.load_memory(0x0000, &[0x11; 4]);
+        sim.load_memory(0x0FFC, &[0x22; 4]);
+        sim.set_write_cycle_polls(0);
+
+        let mut flash = Flash::init(sim.device()).unwrap();
+
+        let mut buf = [0; 8];
+        match flash.read(0x0FFC, &mut buf) {
+            Err(m95320::Error::AddressOutOfBounds { start: 0x0FFC, len: 8 }) => {}
+            other => panic!("read past the end: {:?}", other),
+        };
+        match flash.write_bytes(0x0FFE, &[0xAA; 4]) {
+            Err(m95320::Error::AddressOutOfBounds { start: 0x0FFE, len: 4 }) => {}
+            other => panic!("write past the end: {:?}", other),
+        };
+        match flash.erase_sectors(0x0FC0, 3) {
+            Err(m95320::Error::AddressOutOfBounds { start: 0x0FC0, len: 96 }) => {}
+            other => panic!("erase past the end: {:?}", other),
+        };
+        match flash.erase_sectors(0, usize::MAX) {
+            Err(m95320::Error::AddressOutOfBounds { start: 0, .. }) => {}
+            other => panic!("erase far past the end: {:?}", other),
+        };
+        match flash.read(0x1000, &mut []) {
+            Ok(()) => {}
+            other => panic!("empty read at the end: {:?}", other),
+        };
+
+        let mut memory = [0; 4096];
+        sim.read_memory(0, &mut memory);
+        assert_eq!(memory[..4], [0x11; 4], "nothing was written");
+        assert_eq!(memory[0x0FFC..], [0x22; 4], "nothing was written");
+
+        flash.set_wrap_policy(WrapPolicy::Wrap);
+        flash.read(0x0FFC, &mut buf).expect("read wrapping around");
+        assert_eq!(buf, [0x22, 0x22, 0x22, 0x22, 0x11, 0x11, 0x11, 0x11]);
+
+        flash.write_bytes(0x0FFE, &[0xAA; 4]).expect("write wrapping around");
+        flash.read(0x0FFC, &mut buf).expect("read wrapping around");
+        assert_eq!(buf, [0x22, 0x22, 0xAA, 0xAA, 0xAA, 0xAA, 0x11, 0x11]);
+
+        match flash.read(0x1000, &mut buf) {
+            Err(m95320::Error::AddressOutOfBounds { start: 0x1000, len: 8 }) => {}
+            other => panic!("read starting past the end: {:?}", other),
+        };
+    }
+
+    #[test]
+    fn test_erase() {
+        let sim = Simulator::new();
+        sim.load_memory(0, &[0xAA; 4096]);
+        sim.set_write_cycle_polls(0);
+
+        let mut flash = Flash::init(sim.device()).unwrap();
+
+        match flash.erase_sectors(0x0010, 1) {
+            Err(m95320::Error::NotPageAligned(0x0010)) => {}
+            other => panic!("erase from the middle of a page: {:?}", other),
+        };
+
+        flash.set_erase_fill(0x00);
+        flash.erase_sectors(0x0040, 2).expect("erase");
+        let mut memory = [0; 4096];
+        sim.read_memory(0, &mut memory);
+        assert_eq!(memory[0x0020..0x0040], [0xAA; 32], "page before is untouched");
+        assert_eq!(memory[0x0040..0x0080], [0x00; 64], "two pages erased with the fill byte");
+        assert_eq!(memory[0x0080..0x00A0], [0xAA; 32], "page after is untouched");
+
+        flash.set_erase_fill(0xFF);
+        flash.erase_all().expect("erase all");
+        sim.read_memory(0, &mut memory);
+        assert!(memory.iter().all(|&byte| byte == 0xFF), "all 128 pages erased");
+    }
+
+    #[test]
+    fn test_pending_write() {
+        let sim = Simulator::new();
+        sim.set_write_cycle_polls(2);
+
+        let mut flash = Flash::init(sim.device()).unwrap();
+
+        let data: Vec<u8> = (0..40).collect();
+        let mut write = flash.start_write(30, &data).expect("start write");
+        assert_eq!(write.remaining(), 38, "the first page is sent right away");
+
+        let mut polls = 0;
+        loop {
+            match write.poll() {
+                Ok(()) => break,
+                Err(nb::Error::WouldBlock) => polls += 1,
+                Err(nb::Error::Other(e)) => panic!("write failed: {:?}", e),
+            }
+        }
+        assert_eq!(polls, 3 * 2 + 2, "two busy polls per page, plus one to send each following page");
+
+        let mut buf = [0; 40];
+        flash.read(30, &mut buf).expect("read");
+        assert_eq!(buf[..], data[..], "write across three pages");
+
+        flash.set_protection(ProtectedArea::UpperHalf).expect("protect");
+        match flash.start_write(0x07F0, &data) {
+            Err(m95320::Error::WriteProtected(0x07F0)) => {}
+            other => panic!("expected a protected write, got {:?}", other.map(|write| write.remaining())),
+        };
+    }
+
+    #[test]
+    fn test_deferred_write() {
+        let sim = Simulator::new();
+        sim.set_write_cycle_polls(3);
+
+        let mut flash = Flash::init(sim.device()).unwrap();
+
+        flash.write_bytes(0x0010, &[1, 2, 3]).expect("write");
+        assert!(sim.status().contains(Status::WRITE_IN_PROGRESS), "write returns during the write cycle");
+
+        let mut buf = [0; 3];
+        flash.read(0x0010, &mut buf).expect("read waits for the write cycle");
+        assert_eq!(buf, [1, 2, 3]);
+
+        // Each page is only waited for before the next one is sent
+        flash.write_bytes(0x001E, &[4, 5, 6, 7]).expect("write across pages");
+        assert!(sim.status().contains(Status::WRITE_IN_PROGRESS));
+        flash.flush().expect("flush");
+        assert!(!sim.status().contains(Status::WRITE_IN_PROGRESS), "flush waits for the write cycle");
+        flash.flush().expect("nothing left to flush");
+
+        let mut buf = [0; 4];
+        sim.read_memory(0x001E, &mut buf);
+        assert_eq!(buf, [4, 5, 6, 7]);
+    }
+
+    #[test]
+    fn test_write_interleaved() {
+        use std::cell::RefCell;
+        use std::rc::Rc;
+
+        use embedded_hal::spi::{ErrorType, Operation, SpiDevice};
+
+        /// Logs the slot and instruction of each transaction.
+        struct Slot<S>(usize, S, Rc<RefCell<Vec<(usize, u8)>>>);
+
+        impl<S: SpiDevice> ErrorType for Slot<S> {
+            type Error = S::Error;
+        }
+
+        impl<S: SpiDevice> SpiDevice for Slot<S> {
+            fn transaction(&mut self, operations: &mut [Operation<'_, u8>]) -> Result<(), Self::Error> {
+                if let Some(Operation::Write(data)) = operations.first() {
+                    self.2.borrow_mut().push((self.0, data[0]));
+                }
+                self.1.transaction(operations)
+            }
+        }
+
+        let sims = [Simulator::new(), Simulator::new(), Simulator::new()];
+        let log = Rc::new(RefCell::new(Vec::new()));
+        let mut flashes: Vec<_> = sims.iter().enumerate()
+            .map(|(slot, sim)| {
+                sim.set_write_cycle_polls(3);
+                Flash::init(Slot(slot, sim.device(), log.clone())).unwrap()
+            })
+            .collect();
+
+        let data: Vec<u8> = (0..40).collect();
+        log.borrow_mut().clear();
+        Flash::write_interleaved(&mut flashes, 0x0010, &data).expect("write");
+
+        let writes: Vec<usize> = log.borrow().iter().filter(|(_, opcode)| *opcode == 0x02).map(|(slot, _)| *slot).collect();
+        assert_eq!(writes, vec![0, 1, 2, 0, 1, 2], "each page goes to every chip before the next one");
+        for sim in sims.iter() {
+            assert!(!sim.status().contains(Status::WRITE_IN_PROGRESS), "all write cycles finished");
+            let mut buf = [0; 40];
+            sim.read_memory(0x0010, &mut buf);
+            assert_eq!(buf[..], data[..]);
+        }
+
+        // A protected chip fails the write before anything is sent
+        flashes[1].set_protection(ProtectedArea::UpperHalf).expect("protect");
+        log.borrow_mut().clear();
+        match Flash::write_interleaved(&mut flashes, 0x0800, &data) {
+            Err(m95320::Error::WriteProtected(0x0800)) => {}
+            other => panic!("expected a protected write, got {:?}", other),
+        };
+        assert!(log.borrow().is_empty());
+    }
+
+    #[test]
+    fn test_reattach_after_write() {
+        let sim = Simulator::new();
+        sim.set_write_cycle_polls(3);
+        let mut flash = Flash::init(sim.device()).unwrap();
+
+        flash.write_bytes(0x0010, &[1, 2, 3]).expect("write");
+        assert!(!flash.reattach().expect("reattach waits for the write cycle"));
+
+        // The AT25320 reads as all ones while busy
+        let mut flash = Flash::init_with_chip(sim.device(), AT25320).unwrap();
+        flash.write_bytes(0x0010, &[4, 5, 6]).expect("write");
+        assert!(!flash.reattach().expect("reattach waits for the write cycle"));
+
+        let mut buf = [0; 3];
+        sim.read_memory(0x0010, &mut buf);
+        assert_eq!(buf, [4, 5, 6]);
+    }
+
+    #[test]
+    fn test_hot_plug() {
+        use std::cell::Cell;
+        use std::convert::Infallible;
+        use std::rc::Rc;
+
+        use embedded_hal::spi::{ErrorType, Operation, SpiDevice};
+
+        /// A card socket: reads return the inserted card's data, or the level
+        /// `Q` floats to while the socket is empty.
+        enum Card {
+            Inserted(usize),
+            Floating(u8),
+        }
+
+        struct Socket<'a>(&'a [Simulator], Rc<Cell<Card>>);
+
+        impl ErrorType for Socket<'_> {
+            type Error = Infallible;
+        }
+
+        impl SpiDevice for Socket<'_> {
+            fn transaction(&mut self, operations: &mut [Operation<'_, u8>]) -> Result<(), Infallible> {
+                let card = self.1.replace(Card::Floating(0));
+                let result = match card {
+                    Card::Inserted(index) => self.0[index].device().transaction(operations),
+                    Card::Floating(level) => {
+                        for operation in operations.iter_mut() {
+                            if let Operation::Read(buf) = operation {
+                                buf.fill(level);
+                            }
+                        }
+                        Ok(())
+                    }
+                };
+                self.1.set(card);
+                result
+            }
+        }
+
+        let cards = [Simulator::new(), Simulator::new()];
+        cards[0].load_memory(0x0FF0, b"card A");
+        cards[1].load_memory(0x0FF0, b"card B");
+        let socket = Rc::new(Cell::new(Card::Inserted(0)));
+
+        let mut flash = Flash::init(Socket(&cards, socket.clone())).unwrap();
+        assert!(flash.probe().expect("probe"), "card A inserted");
+        flash.set_fingerprint_region(0x0FF0, 6).expect("set fingerprint");
+
+        socket.set(Card::Floating(0xFF));
+        assert!(!flash.probe().expect("probe"), "Q pulled up");
+        socket.set(Card::Floating(0x00));
+        assert!(!flash.probe().expect("probe"), "Q pulled down");
+        match flash.reattach() {
+            Err(m95320::Error::UnexpectedStatus) => {}
+            other => panic!("expected no chip to answer, got {:?}", other),
+        };
+
+        socket.set(Card::Inserted(0));
+        assert!(!flash.reattach().expect("reattach"), "same card");
+        assert_eq!(cards[0].status(), Status::empty(), "probing leaves the latch reset");
+
+        socket.set(Card::Inserted(1));
+        assert!(flash.reattach().expect("reattach"), "card was changed");
+        assert!(!flash.reattach().expect("reattach"), "the new card is remembered");
+
+        flash.write_bytes(0x0000, &[1, 2, 3]).expect("write to card B");
+        let mut buf = [0; 3];
+        cards[1].read_memory(0x0000, &mut buf);
+        assert_eq!(buf, [1, 2, 3]);
+    }
+}
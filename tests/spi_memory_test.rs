@@ -0,0 +1,49 @@
+//! These tests run code written against the `spi-memory` traits on the driver
+
+use embedded_hal_02::blocking::spi::Transfer;
+use embedded_hal_02::digital::v2::OutputPin;
+use m95320::compat::LegacyDevice;
+use m95320::m95320::Flash;
+use m95320::sim::Simulator;
+use spi_memory::prelude::*;
+
+/// Stores a record the way code written for `spi-memory` flash drivers does.
+fn store_record<F, SPI, CS>(flash: &mut F, addr: u32, record: &mut [u8]) -> Result<(), spi_memory::Error<SPI, CS>>
+where
+    F: Read<u32, SPI, CS> + BlockDevice<u32, SPI, CS>,
+    SPI: Transfer<u8>,
+    CS: OutputPin,
+{
+    flash.erase_sectors(addr, 1)?;
+    flash.write_bytes(addr, record)?;
+
+    let mut buf = [0; 8];
+    flash.read(addr, &mut buf[..record.len()])?;
+    assert_eq!(&buf[..record.len()], &record[..]);
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_spi_memory() {
+        let sim = Simulator::new();
+        sim.load_memory(0x0040, &[0xAA; 32]);
+        let (spi, cs) = sim.split();
+        let mut flash = Flash::init(LegacyDevice::new(spi, cs).unwrap()).unwrap();
+
+        store_record(&mut flash, 0x0040, &mut [1, 2, 3]).expect("store");
+        let mut page = [0; 32];
+        sim.read_memory(0x0040, &mut page);
+        assert_eq!(page[..3], [1, 2, 3]);
+        assert_eq!(page[3..], [0xFF; 29], "the rest of the page is erased");
+
+        // Errors spi-memory has no variant for are reported as an unexpected status
+        match store_record(&mut flash, 0x0041, &mut [4]) {
+            Err(spi_memory::Error::UnexpectedStatus) => {}
+            other => panic!("expected an unexpected status, got {:?}", other),
+        };
+    }
+}
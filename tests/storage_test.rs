@@ -0,0 +1,80 @@
+//! These tests use the driver through the `embedded-storage` traits
+
+use embedded_storage::nor_flash::{MultiwriteNorFlash, NorFlash, NorFlashError, NorFlashErrorKind, ReadNorFlash};
+use embedded_storage::{ReadStorage, Storage};
+use m95320::m95320::{Flash, ProtectedArea, WrapPolicy};
+use m95320::sim::{SimDevice, Simulator};
+
+/// Writes `data` twice without erasing in between, as only multiwrite flash allows.
+fn write_twice<F: MultiwriteNorFlash>(flash: &mut F, offset: u32, data: &[u8]) -> Result<(), F::Error> {
+    flash.write(offset, data)?;
+    flash.write(offset, data)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_storage() {
+        let sim = Simulator::new();
+        let mut flash = Flash::init(sim.device()).unwrap();
+        assert_eq!(ReadStorage::capacity(&flash), 4096);
+
+        Storage::write(&mut flash, 0x001E, &[1, 2, 3, 4]).expect("write across pages");
+        let mut buf = [0; 4];
+        ReadStorage::read(&mut flash, 0x001E, &mut buf).expect("read");
+        assert_eq!(buf, [1, 2, 3, 4]);
+
+        // The storage traits never wrap, whatever the wrap policy
+        flash.set_wrap_policy(WrapPolicy::Wrap);
+        match Storage::write(&mut flash, 0x0FFE, &buf) {
+            Err(m95320::Error::AddressOutOfBounds { start: 0x0FFE, len: 4 }) => {}
+            other => panic!("expected an out of bounds write, got {:?}", other),
+        };
+    }
+
+    #[test]
+    fn test_nor_flash() {
+        let sim = Simulator::new();
+        sim.load_memory(0x0000, &[0xAA; 0x80]);
+        let mut flash = Flash::init(sim.device()).unwrap();
+        assert_eq!((Flash::<SimDevice<'_>>::ERASE_SIZE, Flash::<SimDevice<'_>>::WRITE_SIZE), (32, 1));
+
+        // Erased bytes read as 0xFF, as NOR flash users expect
+        flash.set_erase_fill(0x00);
+        NorFlash::erase(&mut flash, 0x0020, 0x0060).expect("erase two pages");
+        let mut buf = [0; 0x80];
+        ReadNorFlash::read(&mut flash, 0x0000, &mut buf).expect("read");
+        assert_eq!(buf[..0x20], [0xAA; 0x20]);
+        assert_eq!(buf[0x20..0x60], [0xFF; 0x40]);
+        assert_eq!(buf[0x60..], [0xAA; 0x20]);
+
+        write_twice(&mut flash, 0x0021, &[0x12, 0x34]).expect("multiwrite");
+        ReadNorFlash::read(&mut flash, 0x0020, &mut buf[..4]).expect("read");
+        assert_eq!(buf[..4], [0xFF, 0x12, 0x34, 0xFF]);
+
+        let kind = |result: Result<(), m95320::Error<_>>| result.map_err(|e| e.kind());
+        assert_eq!(kind(NorFlash::erase(&mut flash, 0x0010, 0x0040)), Err(NorFlashErrorKind::NotAligned));
+        assert_eq!(kind(NorFlash::erase(&mut flash, 0x0040, 0x0020)), Err(NorFlashErrorKind::OutOfBounds));
+        assert_eq!(kind(NorFlash::erase(&mut flash, 0x0FE0, 0x1020)), Err(NorFlashErrorKind::OutOfBounds));
+        assert_eq!(kind(NorFlash::write(&mut flash, 0x1000, &[0])), Err(NorFlashErrorKind::OutOfBounds));
+    }
+
+    #[test]
+    fn test_protected_erase() {
+        let sim = Simulator::new();
+        sim.load_memory(0x0000, &[0xAA; 0x1000]);
+        let mut flash = Flash::init(sim.device()).unwrap();
+        flash.set_protection(ProtectedArea::UpperQuarter).expect("protect upper quarter");
+
+        // A failed erase leaves the flash as it was, which sequential-storage relies on
+        match NorFlash::erase(&mut flash, 0x0800, 0x0E00) {
+            Err(m95320::Error::WriteProtected(0x0800)) => {}
+            other => panic!("expected a protected erase, got {:?}", other),
+        };
+        let mut buf = [0; 0x1000];
+        ReadNorFlash::read(&mut flash, 0x0000, &mut buf).expect("read");
+        assert!(buf.iter().all(|&byte| byte == 0xAA), "nothing was erased before failing");
+    }
+}